@@ -0,0 +1,349 @@
+use super::network::{self, Validators};
+use super::{game_list, get_game, route, validate_game_id, write_atomic};
+use crate::env::{api_url, asset_download_concurrency, games_path};
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::{schema::GameArtwork, AssetPrefetchResult};
+use futures_util::{future, stream, StreamExt};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+use tokio::fs;
+
+/**
+ * Width and height of the square icon thumbnails
+ */
+const ICON_THUMBNAIL_SIZE: u32 = 256;
+
+/**
+ * Banners wider than this are scaled down to this width for the small banner
+ */
+const BANNER_SMALL_WIDTH: u32 = 640;
+
+/**
+ * A piece of game artwork that is downloaded from the API
+ */
+#[derive(Clone, Copy, Debug)]
+pub enum Asset {
+    Icon,
+    Banner,
+}
+
+impl Asset {
+    /**
+     * Name of the file the asset is stored in, inside the game's directory
+     */
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Asset::Icon => "icon.png",
+            Asset::Banner => "banner.png",
+        }
+    }
+
+    /**
+     * Name of the file the normalized version of the asset is stored in
+     */
+    pub fn variant_file_name(self) -> &'static str {
+        match self {
+            Asset::Icon => "icon_thumb.png",
+            Asset::Banner => "banner_small.png",
+        }
+    }
+
+    /**
+     * Decode an asset and convert it to a PNG, since it is stored as one whatever format the API
+     * sent it in. Returns the full size PNG and its normalized version.
+     *
+     * # Errors
+     * This function will return an error if the bytes are not an image that can be decoded.
+     */
+    fn normalize(self, bytes: &[u8]) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let image = image::load_from_memory(bytes)?;
+        let full = match image::guess_format(bytes)? {
+            ImageFormat::Png => bytes.to_vec(),
+            _ => encode_png(&image)?,
+        };
+        let variant = match self {
+            Asset::Icon => image.resize_to_fill(
+                ICON_THUMBNAIL_SIZE,
+                ICON_THUMBNAIL_SIZE,
+                FilterType::Lanczos3,
+            ),
+            Asset::Banner if image.width() > BANNER_SMALL_WIDTH => {
+                let height = (u64::from(image.height()) * u64::from(BANNER_SMALL_WIDTH)
+                    / u64::from(image.width()))
+                .max(1) as u32;
+                image.resize_exact(BANNER_SMALL_WIDTH, height, FilterType::Lanczos3)
+            }
+            Asset::Banner => image,
+        };
+        Ok((full, encode_png(&variant)?))
+    }
+
+    fn route(self, game_id: &str) -> String {
+        match self {
+            Asset::Icon => route::game_icon(game_id),
+            Asset::Banner => route::game_banner(game_id),
+        }
+    }
+}
+
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, Error> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+/**
+ * Stored next to each downloaded asset to remember which version of the game and artwork it
+ * belongs to.
+ */
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct AssetRecord {
+    /**
+     * Hash of the game the asset was downloaded for
+     */
+    hash: Option<String>,
+
+    /**
+     * Cache validators the server sent with the asset
+     */
+    #[serde(flatten)]
+    validators: Validators,
+}
+
+/**
+ * Download an asset for a game, unless the copy on disk is still current. The copy on disk is
+ * current if the game's hash hasn't changed since it was downloaded, or if the server reports
+ * that the artwork hasn't changed.
+ *
+ * # Errors
+ * This function will return an error if the request fails, the response isn't an image, or if
+ * the filesystem cannot be written to.
+ */
+pub async fn download(game_id: &str, asset: Asset) -> Result<(), Error> {
+    download_for_hash(game_id, asset, current_hash(game_id).await).await
+}
+
+/**
+ * Download an asset for a game again, even if the copy on disk should be current.
+ *
+ * # Errors
+ * This function will return an error if the request fails, the response isn't an image, or if
+ * the filesystem cannot be written to.
+ */
+pub async fn redownload(game_id: &str, asset: Asset) -> Result<(), Error> {
+    validate_game_id(game_id)?;
+    let game_dir = Path::new(games_path().as_str()).join(game_id);
+    // Without the record there are no validators, so the server has to send the asset again
+    let _ = fs::remove_file(game_dir.join(format!("{}.json", asset.file_name()))).await;
+    let _ = fs::remove_file(game_dir.join(asset.file_name())).await;
+    download(game_id, asset).await
+}
+
+/**
+ * Download the icons and banners of several games at once, at most `asset_download_concurrency`
+ * games at a time. If `game_ids` is `None`, the artwork for every game in the game list is
+ * downloaded.
+ *
+ * # Errors
+ * This function will return an error if the game list cannot be fetched. Failures for individual
+ * games are reported in the returned results instead.
+ */
+pub async fn prefetch(game_ids: Option<Vec<String>>) -> Result<Vec<AssetPrefetchResult>, Error> {
+    // The game list has every game's hash, so the hashes aren't looked up one game at a time
+    let listed = game_list().await;
+    let games: Vec<(String, Option<Option<String>>)> = match game_ids {
+        None => listed?
+            .into_iter()
+            .map(|game| (game.id, Some(game.hash)))
+            .collect(),
+        Some(game_ids) => {
+            let hashes = match listed {
+                Ok(games) => Some(
+                    games
+                        .into_iter()
+                        .map(|game| (game.id, game.hash))
+                        .collect::<HashMap<_, _>>(),
+                ),
+                Err(err) => {
+                    log::debug!("Couldn't look up hashes of games: {err}");
+                    None
+                }
+            };
+            game_ids
+                .into_iter()
+                .map(|id| {
+                    let hash = hashes.as_ref().and_then(|hashes| hashes.get(&id).cloned());
+                    (id, hash)
+                })
+                .collect()
+        }
+    };
+    log::info!("Prefetching artwork for {} games", games.len());
+
+    let results = stream::iter(games)
+        .map(|(game_id, hash)| async move {
+            let (icon, banner) = future::join(
+                download_for_hash(game_id.as_str(), Asset::Icon, hash.clone()),
+                download_for_hash(game_id.as_str(), Asset::Banner, hash),
+            )
+            .await;
+            AssetPrefetchResult {
+                game_id,
+                icon_error: icon.err().map(|err| err.to_string()),
+                banner_error: banner.err().map(|err| err.to_string()),
+            }
+        })
+        .buffer_unordered(asset_download_concurrency())
+        .collect::<Vec<_>>()
+        .await;
+
+    let failed = results
+        .iter()
+        .filter(|result| result.icon_error.is_some() || result.banner_error.is_some())
+        .count();
+    if failed > 0 {
+        log::warn!("Failed to prefetch artwork for {failed} games");
+    }
+    Ok(results)
+}
+
+/**
+ * Look up the current hash of a game from the API. Returns `None` if the API can't be reached.
+ */
+async fn current_hash(game_id: &str) -> Option<Option<String>> {
+    match get_game(game_id).await {
+        Ok(game) => Some(game.hash),
+        Err(err) => {
+            log::debug!("Couldn't look up hash for game {game_id}: {err}");
+            None
+        }
+    }
+}
+
+/**
+ * Download an asset for a game, given the game's current hash from `current_hash`.
+ */
+async fn download_for_hash(
+    game_id: &str,
+    asset: Asset,
+    hash: Option<Option<String>>,
+) -> Result<(), Error> {
+    validate_game_id(game_id)?;
+    let game_dir = Path::new(games_path().as_str()).join(game_id);
+    let path = game_dir.join(asset.file_name());
+    let record_path = game_dir.join(format!("{}.json", asset.file_name()));
+
+    let record = if path.exists() {
+        read_record(&record_path).await
+    } else {
+        None
+    };
+
+    let hash = match hash {
+        Some(hash) => hash,
+        None if path.exists() => {
+            log::warn!(
+                "Couldn't check {} {} for updates, keeping the current one",
+                game_id,
+                asset.file_name()
+            );
+            return Ok(());
+        }
+        None => None,
+    };
+
+    let validators = match record {
+        Some(record) => {
+            if record.hash.is_some() && record.hash == hash {
+                log::trace!("{} {} is up to date", game_id, asset.file_name());
+                return ensure_variant(&game_dir, asset).await;
+            }
+            record.validators
+        }
+        None => Validators::default(),
+    };
+
+    let url = format!("{}/{}", api_url(), asset.route(game_id));
+    let validators = match network::request_bytes_if_modified(url.as_str(), &validators).await? {
+        Some((bytes, validators)) => {
+            log::debug!(
+                "Downloaded {} {} ({} bytes)",
+                game_id,
+                asset.file_name(),
+                bytes.len()
+            );
+            let (png, variant) = normalize(asset, bytes).await.map_err(|err| {
+                anyhow!(
+                    "The {} for game {game_id} is not a valid image: {err}",
+                    asset.file_name()
+                )
+            })?;
+            fs::create_dir_all(&game_dir).await?;
+            write_atomic(&path, &png).await?;
+            write_atomic(&game_dir.join(asset.variant_file_name()), &variant).await?;
+            validators
+        }
+        None => {
+            log::debug!("{} {} has not changed", game_id, asset.file_name());
+            ensure_variant(&game_dir, asset).await?;
+            validators
+        }
+    };
+
+    let record = AssetRecord { hash, validators };
+    write_atomic(&record_path, serde_json::to_string(&record)?.as_bytes()).await
+}
+
+/**
+ * Get the paths of the artwork that has been downloaded for a game
+ */
+pub fn artwork(game_id: &str) -> GameArtwork {
+    let game_dir = Path::new(games_path().as_str()).join(game_id);
+    let existing = |file_name: &str| {
+        let path = game_dir.join(file_name);
+        match path.exists() {
+            true => path.to_str().map(String::from),
+            false => None,
+        }
+    };
+    GameArtwork {
+        icon: existing(Asset::Icon.file_name()),
+        icon_thumbnail: existing(Asset::Icon.variant_file_name()),
+        banner: existing(Asset::Banner.file_name()),
+        banner_small: existing(Asset::Banner.variant_file_name()),
+    }
+}
+
+/**
+ * Generate the normalized version of an asset that was downloaded before it existed.
+ *
+ * # Errors
+ * This function will return an error if the asset cannot be read or decoded, or if the
+ * filesystem cannot be written to.
+ */
+async fn ensure_variant(game_dir: &Path, asset: Asset) -> Result<(), Error> {
+    let variant_path = game_dir.join(asset.variant_file_name());
+    if variant_path.exists() {
+        return Ok(());
+    }
+    let bytes = fs::read(game_dir.join(asset.file_name())).await?;
+    let (_, variant) = normalize(asset, bytes).await?;
+    write_atomic(&variant_path, &variant).await
+}
+
+/**
+ * Run `Asset::normalize` on the blocking thread pool, since decoding and scaling images can take
+ * a while.
+ */
+async fn normalize(asset: Asset, bytes: Vec<u8>) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    tokio::task::spawn_blocking(move || asset.normalize(&bytes)).await?
+}
+
+async fn read_record(path: &Path) -> Option<AssetRecord> {
+    let json = fs::read_to_string(path).await.ok()?;
+    serde_json::from_str(json.as_str()).ok()
+}
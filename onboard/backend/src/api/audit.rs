@@ -0,0 +1,315 @@
+use super::assets::{self, Asset};
+use super::install::{self, GAME_JSON};
+use super::launcher::{self, GameLauncher};
+use super::{fetch_and_install, game_from_path, network, route};
+use crate::env::{api_url, games_path};
+use crate::session;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::DevcadeGame;
+use devcade_onboard_types::{InstallIssue, InstallProblem, InstallReport};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/**
+ * How an issue can be repaired
+ */
+enum Repair {
+    /**
+     * Reinstall the installed version from its retained bundle, or download it if that fails
+     */
+    Reinstall,
+    /**
+     * Download the version in the catalog and install it
+     */
+    Download,
+    /**
+     * Download a piece of artwork again
+     */
+    Artwork(Asset),
+    /**
+     * Uninstall the game and delete its directory
+     */
+    Remove,
+}
+
+/**
+ * An issue found with a game, and how to repair it
+ */
+struct Finding {
+    problem: InstallProblem,
+    detail: String,
+    repair: Repair,
+}
+
+impl Finding {
+    fn new(problem: InstallProblem, detail: String, repair: Repair) -> Self {
+        Self {
+            problem,
+            detail,
+            repair,
+        }
+    }
+}
+
+/**
+ * Get every game in the catalog by ID, including ones the game list hides
+ */
+async fn catalog() -> Result<HashMap<String, DevcadeGame>, Error> {
+    let games: Vec<DevcadeGame> =
+        network::request_json(format!("{}/{}", api_url(), route::game_list()).as_str()).await?;
+    Ok(games
+        .into_iter()
+        .map(|game| (game.id.clone(), game))
+        .collect())
+}
+
+/**
+ * Get the directories of every game stored for the current API environment, sorted by game ID
+ *
+ * # Errors
+ * This function will return an error if the games directory cannot be read.
+ */
+pub(super) async fn game_dirs() -> Result<Vec<PathBuf>, Error> {
+    let games_dir = PathBuf::from(games_path());
+    let mut game_dirs = Vec::new();
+    if !games_dir.exists() {
+        return Ok(game_dirs);
+    }
+    let mut entries = fs::read_dir(&games_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.is_dir() {
+            game_dirs.push(path);
+        }
+    }
+    game_dirs.sort();
+    Ok(game_dirs)
+}
+
+/**
+ * Check the icon and banner of a game
+ */
+fn check_artwork(game_dir: &Path, findings: &mut Vec<Finding>) {
+    for asset in [Asset::Icon, Asset::Banner] {
+        let path = game_dir.join(asset.file_name());
+        if !path.exists() {
+            findings.push(Finding::new(
+                InstallProblem::Missing,
+                format!("{} has not been downloaded", asset.file_name()),
+                Repair::Artwork(asset),
+            ));
+        } else if let Err(err) = image::image_dimensions(&path) {
+            findings.push(Finding::new(
+                InstallProblem::Corrupt,
+                format!("{} is not a valid image: {err}", asset.file_name()),
+                Repair::Artwork(asset),
+            ));
+        }
+    }
+}
+
+/**
+ * Check everything installed for one game. `listed` is the game's catalog entry, or `None` if
+ * the catalog couldn't be fetched, or `Some(None)` if the game isn't in it.
+ */
+async fn check_game(
+    game_id: &str,
+    game_dir: &Path,
+    listed: Option<Option<&DevcadeGame>>,
+    launcher: &dyn GameLauncher,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if matches!(listed, Some(None)) {
+        findings.push(Finding::new(
+            InstallProblem::Orphaned,
+            String::from("The game is not in the catalog anymore"),
+            Repair::Remove,
+        ));
+        return findings;
+    }
+    check_artwork(game_dir, &mut findings);
+
+    let game_json = game_dir.join(GAME_JSON);
+    if !game_json.exists() {
+        // Only the artwork has been downloaded, the game is installed when it is first launched
+        return findings;
+    }
+    let game = match game_from_path(&game_json) {
+        Ok(game) => game,
+        Err(err) => {
+            findings.push(Finding::new(
+                InstallProblem::Corrupt,
+                format!("{GAME_JSON} can't be read: {err}"),
+                Repair::Download,
+            ));
+            return findings;
+        }
+    };
+    if game.id != game_id {
+        findings.push(Finding::new(
+            InstallProblem::Corrupt,
+            format!("{GAME_JSON} describes game {}", game.id),
+            Repair::Download,
+        ));
+        return findings;
+    }
+
+    let bundle = install::retained_bundle(game_dir, &game.hash);
+    if bundle.is_none() {
+        findings.push(Finding::new(
+            InstallProblem::Missing,
+            format!("The bundle of version {:?} is not retained", game.hash),
+            Repair::Download,
+        ));
+    }
+    if let Err(err) = launcher.verify(&game, game_dir, bundle.as_deref()).await {
+        findings.push(Finding::new(
+            InstallProblem::Corrupt,
+            format!("The {} install is damaged: {err}", launcher.name()),
+            Repair::Reinstall,
+        ));
+    }
+
+    if let Some(Some(listed)) = listed {
+        if listed.hash != game.hash && !install::is_rolled_back(game_dir, &listed.hash).await {
+            findings.push(Finding::new(
+                InstallProblem::Outdated,
+                format!(
+                    "Version {:?} is installed, the catalog has {:?}",
+                    game.hash, listed.hash
+                ),
+                Repair::Download,
+            ));
+        }
+    }
+    findings
+}
+
+/**
+ * Repair an issue with a game. Returns whether the game was (re)installed, which repairs
+ * everything but its artwork.
+ */
+async fn repair(
+    repair: &Repair,
+    game_id: &str,
+    game_dir: &Path,
+    listed: Option<&DevcadeGame>,
+    launcher: &dyn GameLauncher,
+) -> Result<bool, Error> {
+    let download = || async {
+        let game = listed.cloned().ok_or_else(|| {
+            anyhow!("Game {game_id} can't be downloaded, the catalog can't be reached")
+        })?;
+        fetch_and_install(game_dir, game, launcher).await
+    };
+    match repair {
+        Repair::Artwork(asset) => {
+            assets::redownload(game_id, *asset).await?;
+            Ok(false)
+        }
+        Repair::Download => {
+            download().await?;
+            Ok(true)
+        }
+        Repair::Reinstall => {
+            let game = game_from_path(&game_dir.join(GAME_JSON))?;
+            if let Err(err) = install::verify(game_dir, game, launcher).await {
+                log::warn!("Couldn't reinstall {game_id} from its bundle, downloading it: {err}");
+                download().await?;
+            }
+            Ok(true)
+        }
+        Repair::Remove => {
+            if let Ok(game) = game_from_path(&game_dir.join(GAME_JSON)) {
+                launcher.uninstall(&game).await?;
+            }
+            fs::remove_dir_all(game_dir).await?;
+            log::info!("Removed {game_id}, which is not in the catalog anymore");
+            Ok(true)
+        }
+    }
+}
+
+/**
+ * Check that every game installed for the current API environment is consistent: that its
+ * `game.json`, retained bundle, artwork and launcher install are all there and usable, and that
+ * it is still in the catalog and up to date. If `repair` is set, every issue found is repaired
+ * as well, except for the game that is running.
+ *
+ * # Errors
+ * This function will return an error if the games directory cannot be read. Failed repairs are
+ * reported in the returned report instead.
+ */
+pub async fn verify_installations(repair_issues: bool) -> Result<InstallReport, Error> {
+    let game_dirs = game_dirs().await?;
+    let launcher = launcher::current();
+    let catalog = match catalog().await {
+        Ok(catalog) => Some(catalog),
+        Err(err) => {
+            log::warn!("Couldn't fetch the catalog, only checking local installs: {err}");
+            None
+        }
+    };
+    let running = session::current().map(|session| session.game_id);
+
+    let mut report = InstallReport {
+        catalog_checked: catalog.is_some(),
+        ..InstallReport::default()
+    };
+    for game_dir in game_dirs {
+        let Some(game_id) = game_dir.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        report.games_checked += 1;
+        let listed = catalog.as_ref().map(|catalog| catalog.get(game_id));
+        let findings = check_game(game_id, &game_dir, listed, launcher.as_ref()).await;
+
+        // Once the game has been installed again, only its artwork can still be wrong
+        let mut reinstalled = false;
+        for finding in findings {
+            let mut issue = InstallIssue {
+                game_id: game_id.to_owned(),
+                problem: finding.problem,
+                detail: finding.detail,
+                repaired: false,
+                repair_error: None,
+            };
+            if repair_issues {
+                if running.as_deref() == Some(game_id) {
+                    issue.repair_error = Some(String::from("The game is running"));
+                } else if reinstalled && !matches!(finding.repair, Repair::Artwork(_)) {
+                    issue.repaired = true;
+                } else {
+                    let listed = listed.flatten();
+                    match repair(
+                        &finding.repair,
+                        game_id,
+                        &game_dir,
+                        listed,
+                        launcher.as_ref(),
+                    )
+                    .await
+                    {
+                        Ok(installed) => {
+                            reinstalled |= installed;
+                            issue.repaired = true;
+                        }
+                        Err(err) => {
+                            log::error!("Failed to repair {game_id}: {err}");
+                            issue.repair_error = Some(err.to_string());
+                        }
+                    }
+                }
+            }
+            report.issues.push(issue);
+        }
+    }
+
+    log::info!(
+        "Checked {} installed games, found {} issues",
+        report.games_checked,
+        report.issues.len()
+    );
+    Ok(report)
+}
@@ -0,0 +1,108 @@
+use super::audit::game_dirs;
+use super::game_from_path;
+use super::install::{self, GAME_JSON, PREVIOUS_JSON, STAGING_DIR};
+use super::launcher;
+use anyhow::Error;
+use devcade_onboard_types::GcReport;
+use std::path::Path;
+use std::time::Duration;
+use tokio::fs;
+
+/**
+ * How long a staging directory has to be left alone before it counts as the leftovers of an
+ * interrupted install, rather than an install that is still going
+ */
+const STALE_STAGING: Duration = Duration::from_secs(60 * 60);
+
+/**
+ * Get the size of a file, or of everything in a directory
+ */
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/**
+ * Delete a file or directory, and add it to the report
+ */
+async fn remove(path: &Path, report: &mut GcReport) {
+    let size = disk_usage(path);
+    let removed = match path.is_dir() {
+        true => fs::remove_dir_all(path).await,
+        false => fs::remove_file(path).await,
+    };
+    match removed {
+        Ok(()) => {
+            log::debug!("Removed {path:?} ({size} bytes)");
+            report
+                .files_removed
+                .push(path.to_string_lossy().into_owned());
+            report.bytes_reclaimed += size;
+        }
+        Err(err) => log::warn!("Failed to remove {path:?}: {err}"),
+    }
+}
+
+/**
+ * Whether a staging directory was left behind by an install that didn't finish
+ */
+fn is_stale(staging_dir: &Path) -> bool {
+    std::fs::metadata(staging_dir)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age >= STALE_STAGING)
+}
+
+/**
+ * Free up disk space used for games of the current API environment that aren't needed anymore:
+ * bundles of versions that can't be rolled back to, leftovers of interrupted installs, and
+ * whatever the launcher installed for games that have been removed, like flatpak apps and the
+ * runtimes only they used.
+ *
+ * # Errors
+ * This function will return an error if the games directory cannot be read, or if the launcher
+ * fails to uninstall something.
+ */
+pub async fn collect_garbage() -> Result<GcReport, Error> {
+    let launcher = launcher::current();
+    let mut report = GcReport::default();
+    let mut games = Vec::new();
+    for game_dir in game_dirs().await? {
+        let staging_dir = game_dir.join(STAGING_DIR);
+        if staging_dir.exists() && is_stale(&staging_dir) {
+            remove(&staging_dir, &mut report).await;
+        }
+        for bundle in install::unneeded_bundles(&game_dir).await {
+            remove(&bundle, &mut report).await;
+        }
+        // The previous version is still installed as far as rollbacks are concerned
+        games.extend(
+            [GAME_JSON, PREVIOUS_JSON]
+                .iter()
+                .filter_map(|file| game_from_path(&game_dir.join(file)).ok()),
+        );
+    }
+
+    launcher.collect_garbage(&games, &mut report).await?;
+    log::info!(
+        "Removed {} files, {} apps and {} runtimes, reclaiming {} bytes",
+        report.files_removed.len(),
+        report.apps_uninstalled.len(),
+        report.runtimes_uninstalled.len(),
+        report.bytes_reclaimed
+    );
+    Ok(report)
+}
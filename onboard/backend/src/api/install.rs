@@ -0,0 +1,380 @@
+use super::launcher::{self, GameLauncher, APPS_JSON};
+use super::{game_from_path, write_atomic};
+use crate::env::{devcade_path, games_path, games_path_for, state_path_for};
+use crate::session::{self, crash::CRASHES_DIR, logs::LOGS_DIR, stats::STATS_JSON};
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::DevcadeGame;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/**
+ * Metadata of the installed version of the game
+ */
+pub const GAME_JSON: &str = "game.json";
+
+/**
+ * Metadata of the version that was installed before the current one, kept so it can be rolled
+ * back to
+ */
+pub const PREVIOUS_JSON: &str = "previous.json";
+
+/**
+ * Directory a new version is downloaded into before it is installed
+ */
+pub const STAGING_DIR: &str = "staging";
+
+/**
+ * Directory the bundles of the current and previous versions are kept in, named by game hash
+ */
+pub const VERSIONS_DIR: &str = "versions";
+
+/**
+ * Where older versions of the backend kept the bundle of the installed version
+ */
+pub const LEGACY_BUNDLE: &str = "bundle.flatpak";
+
+/**
+ * Written when a game is rolled back, so the version that was rolled back from isn't
+ * immediately installed again
+ */
+pub const ROLLBACK_JSON: &str = "rollback.json";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct RollbackRecord {
+    /**
+     * Hash of the version that was rolled back from
+     */
+    skipped_hash: Option<String>,
+}
+
+/**
+ * Path of the retained bundle for a version of a game
+ */
+pub fn bundle_path(game_dir: &Path, hash: &Option<String>) -> PathBuf {
+    let hash = hash
+        .as_deref()
+        .unwrap_or("unknown")
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>();
+    game_dir.join(VERSIONS_DIR).join(format!("{hash}.flatpak"))
+}
+
+/**
+ * Path of the bundle retained for a version of a game, if there is one
+ */
+pub fn retained_bundle(game_dir: &Path, hash: &Option<String>) -> Option<PathBuf> {
+    let bundle = bundle_path(game_dir, hash);
+    if bundle.exists() {
+        return Some(bundle);
+    }
+    // Installs from older versions of the backend kept the bundle next to game.json
+    let legacy_bundle = game_dir.join(LEGACY_BUNDLE);
+    legacy_bundle.exists().then_some(legacy_bundle)
+}
+
+/**
+ * Whether `hash` is a version of the game that was rolled back from, and shouldn't be installed
+ * again until a newer version is uploaded.
+ */
+pub async fn is_rolled_back(game_dir: &Path, hash: &Option<String>) -> bool {
+    let Ok(json) = fs::read_to_string(game_dir.join(ROLLBACK_JSON)).await else {
+        return false;
+    };
+    match serde_json::from_str::<RollbackRecord>(json.as_str()) {
+        Ok(record) => record.skipped_hash.is_some() && &record.skipped_hash == hash,
+        Err(_) => false,
+    }
+}
+
+/**
+ * Install a new version of a game from its bundle. The bundle is staged and installed with the
+ * launcher first, and `game.json` is only replaced once that has succeeded, so a failed install
+ * leaves the previously installed version untouched. The version being replaced is retained so
+ * it can be rolled back to with `rollback`.
+ *
+ * # Errors
+ * This function will return an error if the bundle cannot be installed, or if the filesystem
+ * cannot be written to.
+ */
+pub async fn install(
+    game_dir: &Path,
+    mut game: DevcadeGame,
+    bundle: &[u8],
+    launcher: &dyn GameLauncher,
+) -> Result<DevcadeGame, Error> {
+    let staging_dir = game_dir.join(STAGING_DIR);
+    if staging_dir.exists() {
+        log::warn!(
+            "Removing leftovers of an interrupted install of {}",
+            game.id
+        );
+        fs::remove_dir_all(&staging_dir).await?;
+    }
+    fs::create_dir_all(&staging_dir).await?;
+    let staged_bundle = staging_dir.join(LEGACY_BUNDLE);
+    fs::write(&staged_bundle, bundle).await?;
+
+    if let Err(err) = launcher.install(&mut game, &staged_bundle).await {
+        log::error!("Failed to install {}, keeping the current version", game.id);
+        let _ = fs::remove_dir_all(&staging_dir).await;
+        return Err(err);
+    }
+
+    if let Err(err) = promote(game_dir, &game).await {
+        log::error!("Failed to swap in the new version of {}: {err}", game.id);
+        // The launcher already has the new version, so put back the one game.json still describes
+        if let Err(err) = restore_current(game_dir, launcher).await {
+            log::error!(
+                "Failed to restore the current version of {}: {err}",
+                game.id
+            );
+        }
+        let _ = fs::remove_dir_all(&staging_dir).await;
+        return Err(err);
+    }
+    Ok(game)
+}
+
+/**
+ * Make an installed version of a game the current version, retaining the version it replaces.
+ */
+async fn promote(game_dir: &Path, game: &DevcadeGame) -> Result<(), Error> {
+    let versions_dir = game_dir.join(VERSIONS_DIR);
+    fs::create_dir_all(&versions_dir).await?;
+    let bundle = bundle_path(game_dir, &game.hash);
+    fs::rename(game_dir.join(STAGING_DIR).join(LEGACY_BUNDLE), &bundle).await?;
+
+    let previous = game_from_path(&game_dir.join(GAME_JSON)).ok();
+    if let Some(previous) = &previous {
+        if previous.hash != game.hash {
+            // Installs from older versions of the backend kept the bundle next to game.json
+            let legacy_bundle = game_dir.join(LEGACY_BUNDLE);
+            let previous_bundle = bundle_path(game_dir, &previous.hash);
+            if legacy_bundle.exists() && !previous_bundle.exists() {
+                fs::rename(&legacy_bundle, &previous_bundle).await?;
+            }
+            write_atomic(
+                &game_dir.join(PREVIOUS_JSON),
+                serde_json::to_string(previous)?.as_bytes(),
+            )
+            .await?;
+        }
+    }
+
+    // This is the actual swap, everything before it can fail without changing the current version
+    write_atomic(
+        &game_dir.join(GAME_JSON),
+        serde_json::to_string(game)?.as_bytes(),
+    )
+    .await?;
+    log::debug!("Swapped in version {:?} of {}", game.hash, game.id);
+
+    let _ = fs::remove_file(game_dir.join(ROLLBACK_JSON)).await;
+    let _ = fs::remove_file(game_dir.join(LEGACY_BUNDLE)).await;
+    let _ = fs::remove_dir_all(game_dir.join(STAGING_DIR)).await;
+    prune_versions(game_dir).await;
+    Ok(())
+}
+
+/**
+ * Reinstall the bundle of the version described by the game's `game.json`.
+ */
+async fn restore_current(game_dir: &Path, launcher: &dyn GameLauncher) -> Result<(), Error> {
+    let mut current = game_from_path(&game_dir.join(GAME_JSON))?;
+    let bundle = retained_bundle(game_dir, &current.hash)
+        .ok_or_else(|| anyhow!("No bundle retained for version {:?}", current.hash))?;
+    launcher.install(&mut current, &bundle).await
+}
+
+/**
+ * Check that the installed version of a game is intact before it is launched: that `game.json`
+ * can be read and describes `game` in `game_dir`, and that the launcher still has the version it
+ * describes, as recorded when it was installed. If the launcher's install is missing or doesn't
+ * match, the version is reinstalled from its retained bundle. Returns the game as it should be
+ * launched.
+ *
+ * # Errors
+ * This function will return an error if the install is damaged and can't be repaired from the
+ * retained bundle, in which case the game should be downloaded again.
+ */
+pub async fn verify(
+    game_dir: &Path,
+    game: DevcadeGame,
+    launcher: &dyn GameLauncher,
+) -> Result<DevcadeGame, Error> {
+    let installed = game_from_path(&game_dir.join(GAME_JSON))
+        .map_err(|err| anyhow!("{GAME_JSON} in {game_dir:?} can't be read: {err}"))?;
+    let dir_name = game_dir.file_name().and_then(|name| name.to_str());
+    if dir_name != Some(installed.id.as_str()) || installed.id != game.id {
+        return Err(anyhow!(
+            "{GAME_JSON} in {game_dir:?} describes game {}",
+            installed.id
+        ));
+    }
+    if installed.hash != game.hash {
+        return Err(anyhow!(
+            "{GAME_JSON} in {game_dir:?} describes version {:?}, not {:?}",
+            installed.hash,
+            game.hash
+        ));
+    }
+    let mut game = installed;
+    let bundle = retained_bundle(game_dir, &game.hash);
+    let Err(err) = launcher.verify(&game, game_dir, bundle.as_deref()).await else {
+        return Ok(game);
+    };
+
+    log::warn!("The install of {} is damaged: {err}", game.id);
+    let bundle = bundle.ok_or_else(|| anyhow!("No bundle retained for version {:?}", game.hash))?;
+    log::info!("Reinstalling {} from {bundle:?}", game.id);
+    launcher.install(&mut game, &bundle).await?;
+    // Reinstalling can change what the launcher filled in, like the flatpak app ID
+    write_atomic(
+        &game_dir.join(GAME_JSON),
+        serde_json::to_string(&game)?.as_bytes(),
+    )
+    .await?;
+    launcher.verify(&game, game_dir, Some(&bundle)).await?;
+    log::info!("Repaired the install of {}", game.id);
+    Ok(game)
+}
+
+/**
+ * Get the retained bundles that aren't needed anymore: everything but the bundles of the current
+ * and previous versions, and the bundle older versions of the backend kept next to `game.json`
+ * once the current version's bundle is retained the new way.
+ */
+pub async fn unneeded_bundles(game_dir: &Path) -> Vec<PathBuf> {
+    let versions = [GAME_JSON, PREVIOUS_JSON]
+        .iter()
+        .filter_map(|file| game_from_path(&game_dir.join(file)).ok())
+        .map(|game| bundle_path(game_dir, &game.hash))
+        .collect::<Vec<PathBuf>>();
+    let mut unneeded = Vec::new();
+    let legacy_bundle = game_dir.join(LEGACY_BUNDLE);
+    if legacy_bundle.exists() && versions.first().is_some_and(|current| current.exists()) {
+        unneeded.push(legacy_bundle);
+    }
+    let Ok(mut entries) = fs::read_dir(game_dir.join(VERSIONS_DIR)).await else {
+        return unneeded;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if !versions.contains(&entry.path()) {
+            unneeded.push(entry.path());
+        }
+    }
+    unneeded
+}
+
+/**
+ * Delete retained bundles other than the current and previous versions.
+ */
+async fn prune_versions(game_dir: &Path) {
+    for bundle in unneeded_bundles(game_dir).await {
+        log::debug!("Removing old bundle {bundle:?}");
+        let _ = fs::remove_file(bundle).await;
+    }
+}
+
+/**
+ * Reinstall the version of a game that was installed before the current one. The current
+ * version becomes the previous version, so rolling back again undoes the rollback. The version
+ * that was rolled back from won't be downloaded again until a newer version is uploaded.
+ *
+ * # Errors
+ * This function will return an error if the game is running, if there is no previous version to
+ * roll back to, or if it cannot be installed.
+ */
+pub async fn rollback(game_id: &str) -> Result<DevcadeGame, Error> {
+    if session::current().is_some_and(|session| session.game_id == game_id) {
+        return Err(anyhow!(
+            "Can't roll back game {game_id} while it is running"
+        ));
+    }
+    let game_dir = Path::new(games_path().as_str()).join(game_id);
+    let launcher = launcher::current();
+
+    let current = game_from_path(&game_dir.join(GAME_JSON))
+        .map_err(|err| anyhow!("Game {game_id} is not installed: {err}"))?;
+    let mut previous = game_from_path(&game_dir.join(PREVIOUS_JSON))
+        .map_err(|_| anyhow!("Game {game_id} has no previous version to roll back to"))?;
+    let bundle = bundle_path(&game_dir, &previous.hash);
+    if !bundle.exists() {
+        return Err(anyhow!(
+            "The bundle for the previous version of {game_id} is missing"
+        ));
+    }
+
+    log::info!(
+        "Rolling back {game_id} from version {:?} to {:?}",
+        current.hash,
+        previous.hash
+    );
+    launcher.install(&mut previous, &bundle).await?;
+
+    write_atomic(
+        &game_dir.join(PREVIOUS_JSON),
+        serde_json::to_string(&current)?.as_bytes(),
+    )
+    .await?;
+    write_atomic(
+        &game_dir.join(GAME_JSON),
+        serde_json::to_string(&previous)?.as_bytes(),
+    )
+    .await?;
+    let record = RollbackRecord {
+        skipped_hash: current.hash,
+    };
+    write_atomic(
+        &game_dir.join(ROLLBACK_JSON),
+        serde_json::to_string(&record)?.as_bytes(),
+    )
+    .await?;
+    Ok(previous)
+}
+
+/**
+ * Move what older versions of the backend kept in the devcade directory into the games and state
+ * directories of each API environment. Production games used to live directly in the devcade
+ * directory, so only directories with a `game.json` are moved and everything else is left alone.
+ *
+ * # Errors
+ * This function will return an error if the devcade directory can't be read, or something can't
+ * be moved.
+ */
+pub async fn migrate_layout() -> Result<(), Error> {
+    let devcade_dir = PathBuf::from(devcade_path());
+    let mut moves = Vec::new();
+    for (old_dir, production) in [
+        (devcade_dir.clone(), true),
+        (devcade_dir.join("development"), false),
+    ] {
+        let state_dir = PathBuf::from(state_path_for(production));
+        // Session logs and crash reports used to be shared by both environments, so they all end
+        // up in production
+        for file in [STATS_JSON, APPS_JSON, LOGS_DIR, CRASHES_DIR] {
+            moves.push((old_dir.join(file), state_dir.join(file)));
+        }
+    }
+    let mut entries = fs::read_dir(&devcade_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.path().join(GAME_JSON).is_file() {
+            let to = Path::new(games_path_for(true).as_str()).join(entry.file_name());
+            moves.push((entry.path(), to));
+        }
+    }
+
+    for (from, to) in moves {
+        if !from.exists() || to.exists() {
+            continue;
+        }
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(&from, &to).await?;
+        log::info!("Moved {} to {}", from.display(), to.display());
+    }
+    Ok(())
+}
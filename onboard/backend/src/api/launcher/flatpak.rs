@@ -0,0 +1,441 @@
+use super::GameLauncher;
+use crate::api::{
+    flatpak_installation, install_flatpak_bundle_async, limits, sandbox, write_atomic,
+};
+use crate::config::ResourceLimits;
+use crate::env::state_path;
+use crate::policy;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::{schema::DevcadeGame, GcReport, TerminationStage};
+use futures_util::future::BoxFuture;
+use libflatpak::{gio, prelude::*, BundleRef, RefKind, Transaction};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::{Child, Command};
+
+/**
+ * Apps the backend has installed into the user installation, kept in the state directory. Other
+ * apps in it aren't games, and are never uninstalled.
+ */
+pub const APPS_JSON: &str = "flatpak_apps.json";
+
+/**
+ * Installs games as flatpak apps and runs them in their sandbox. This is what the cabinet uses.
+ */
+pub struct FlatpakLauncher {
+    /**
+     * Separate user installation to use instead of the default one, from `flatpak_user_dir`
+     */
+    user_dir: Option<String>,
+}
+
+impl FlatpakLauncher {
+    #[must_use]
+    pub fn new(user_dir: Option<String>) -> Self {
+        Self { user_dir }
+    }
+
+    /**
+     * Build a `flatpak` command that operates on this launcher's installation
+     */
+    fn command(&self) -> Command {
+        let mut command = Command::new("flatpak");
+        if let Some(dir) = &self.user_dir {
+            command.env("FLATPAK_USER_DIR", dir);
+        }
+        command
+    }
+
+    /**
+     * Kill every instance of an app with `flatpak kill`
+     *
+     * # Errors
+     * This function will return an error if `flatpak kill` cannot be run.
+     */
+    pub async fn kill_app(&self, app_id: &str) -> Result<(), Error> {
+        self.command()
+            .arg("kill")
+            .arg(app_id)
+            .spawn()?
+            .wait()
+            .await?;
+        Ok(())
+    }
+}
+
+/**
+ * Get the apps the backend has installed, see [`APPS_JSON`]
+ */
+async fn installed_apps() -> BTreeSet<String> {
+    let path = Path::new(state_path().as_str()).join(APPS_JSON);
+    match tokio::fs::read_to_string(path).await {
+        Ok(json) => serde_json::from_str(json.as_str()).unwrap_or_default(),
+        Err(_) => BTreeSet::new(),
+    }
+}
+
+async fn write_installed_apps(apps: &BTreeSet<String>) -> Result<(), Error> {
+    let dir = PathBuf::from(state_path());
+    tokio::fs::create_dir_all(&dir).await?;
+    write_atomic(
+        &dir.join(APPS_JSON),
+        serde_json::to_string(apps)?.as_bytes(),
+    )
+    .await
+}
+
+/**
+ * Remember that the backend installed an app, so it can be uninstalled once no game uses it
+ */
+async fn record_app(app_id: &str) -> Result<(), Error> {
+    let mut apps = installed_apps().await;
+    if apps.insert(app_id.to_owned()) {
+        write_installed_apps(&apps).await?;
+    }
+    Ok(())
+}
+
+/**
+ * Uninstall the apps no game uses, and then the runtimes no app uses. If `owned` is set, the
+ * installation is shared with other apps: only apps in `owned` are uninstalled, and runtimes are
+ * left alone, since the backend can't tell which of them it installed.
+ */
+fn uninstall_unused(
+    user_dir: Option<&str>,
+    used: &HashSet<String>,
+    owned: Option<&BTreeSet<String>>,
+    report: &mut GcReport,
+) -> Result<(), Error> {
+    let installation = flatpak_installation(user_dir)?;
+
+    let transaction = Transaction::for_installation(&installation, None::<&gio::Cancellable>)?;
+    transaction.set_no_interaction(true);
+    let mut apps = Vec::new();
+    for app in installation.list_installed_refs_by_kind(RefKind::App, None::<&gio::Cancellable>)? {
+        let (Some(name), Some(app_ref)) = (app.name(), app.format_ref()) else {
+            continue;
+        };
+        if used.contains(name.as_str()) || owned.is_some_and(|owned| !owned.contains(name.as_str()))
+        {
+            continue;
+        }
+        log::info!("Uninstalling {name}, no installed game uses it");
+        transaction.add_uninstall(app_ref.as_str())?;
+        apps.push((name.to_string(), app.installed_size()));
+    }
+    if !apps.is_empty() {
+        transaction.run(None::<&gio::Cancellable>)?;
+    }
+    for (name, size) in apps {
+        report.apps_uninstalled.push(name);
+        report.bytes_reclaimed += size;
+    }
+
+    if owned.is_some() {
+        return Ok(());
+    }
+
+    // Runtimes only become unused once the apps using them are gone, so this is a second
+    // transaction
+    let transaction = Transaction::for_installation(&installation, None::<&gio::Cancellable>)?;
+    transaction.set_no_interaction(true);
+    let mut runtimes = Vec::new();
+    for runtime in installation.list_unused_refs(None, None::<&gio::Cancellable>)? {
+        if runtime.kind() != RefKind::Runtime {
+            continue;
+        }
+        let Some(runtime_ref) = runtime.format_ref() else {
+            continue;
+        };
+        log::info!("Uninstalling runtime {runtime_ref}, no installed app uses it");
+        transaction.add_uninstall(runtime_ref.as_str())?;
+        runtimes.push((runtime_ref.to_string(), runtime.installed_size()));
+    }
+    if !runtimes.is_empty() {
+        transaction.run(None::<&gio::Cancellable>)?;
+    }
+    for (runtime_ref, size) in runtimes {
+        report.runtimes_uninstalled.push(runtime_ref);
+        report.bytes_reclaimed += size;
+    }
+    Ok(())
+}
+
+/**
+ * Get the commit of the app in a flatpak bundle
+ */
+fn bundle_commit(bundle: &Path) -> Result<String, Error> {
+    BundleRef::new(&gio::File::for_path(bundle))?
+        .commit()
+        .map(|commit| commit.to_string())
+        .ok_or_else(|| anyhow!("Flatpak bundle {bundle:?} has no commit"))
+}
+
+/**
+ * Check that a flatpak app is installed at the commit recorded for the game, or the commit of
+ * `bundle` if the game was installed before commits were recorded. An install that can't be
+ * compared with either isn't trusted.
+ */
+fn verify_app(
+    user_dir: Option<&str>,
+    app_id: &str,
+    recorded: Option<&str>,
+    bundle: Option<&Path>,
+) -> Result<(), Error> {
+    let installed = flatpak_installation(user_dir)?
+        .installed_ref(RefKind::App, app_id, None, None, None::<&gio::Cancellable>)
+        .map_err(|err| anyhow!("Flatpak app {app_id} is not installed: {err}"))?;
+    let expected = match (recorded, bundle) {
+        (Some(commit), _) => commit.to_owned(),
+        (None, Some(bundle)) => bundle_commit(bundle)?,
+        (None, None) => {
+            return Err(anyhow!(
+                "Can't check flatpak app {app_id}, no commit is recorded and no bundle is retained"
+            ))
+        }
+    };
+    let installed = installed.commit().map(|commit| commit.to_string());
+    if installed.as_deref() != Some(expected.as_str()) {
+        return Err(anyhow!(
+            "Flatpak app {app_id} is at commit {installed:?}, but {expected:?} was installed"
+        ));
+    }
+    Ok(())
+}
+
+/**
+ * Uninstall a flatpak app. Its runtime is left installed, other games may use it.
+ */
+fn uninstall_app(user_dir: Option<&str>, app_id: &str) -> Result<(), Error> {
+    let installation = flatpak_installation(user_dir)?;
+    let installed = match installation.installed_ref(
+        RefKind::App,
+        app_id,
+        None,
+        None,
+        None::<&gio::Cancellable>,
+    ) {
+        Ok(installed) => installed,
+        Err(_) => {
+            log::debug!("Flatpak app {app_id} is already uninstalled");
+            return Ok(());
+        }
+    };
+    let app_ref = installed
+        .format_ref()
+        .ok_or_else(|| anyhow!("Flatpak app {app_id} has no ref"))?;
+    let transaction = Transaction::for_installation(&installation, None::<&gio::Cancellable>)?;
+    transaction.set_no_interaction(true);
+    transaction.add_uninstall(app_ref.as_str())?;
+    transaction.run(None::<&gio::Cancellable>)?;
+    log::info!("Uninstalled flatpak app {app_id}");
+    Ok(())
+}
+
+/**
+ * Get the flatpak app ID a game was installed as
+ */
+fn app_id(game: &DevcadeGame) -> Result<&str, Error> {
+    game.flatpak_app_id
+        .as_deref()
+        .ok_or_else(|| anyhow!("Game {} has no flatpak app id", game.id))
+}
+
+impl GameLauncher for FlatpakLauncher {
+    fn name(&self) -> &'static str {
+        "flatpak"
+    }
+
+    fn install<'a>(
+        &'a self,
+        game: &'a mut DevcadeGame,
+        bundle: &'a Path,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let rules = policy::for_game(&policy::current()?, game.id.as_str());
+            let app_id =
+                install_flatpak_bundle_async(bundle.to_path_buf(), self.user_dir.clone(), rules)
+                    .await?;
+            log::info!("Installed {} as flatpak app {app_id}", game.id);
+            record_app(app_id.as_str()).await?;
+            let bundle = bundle.to_path_buf();
+            game.flatpak_commit =
+                Some(tokio::task::spawn_blocking(move || bundle_commit(&bundle)).await??);
+            game.flatpak_app_id = Some(app_id);
+            Ok(())
+        })
+    }
+
+    fn uninstall<'a>(&'a self, game: &'a DevcadeGame) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let app_id = app_id(game)?.to_owned();
+            let user_dir = self.user_dir.clone();
+            tokio::task::spawn_blocking(move || uninstall_app(user_dir.as_deref(), app_id.as_str()))
+                .await?
+        })
+    }
+
+    fn collect_garbage<'a>(
+        &'a self,
+        games: &'a [DevcadeGame],
+        report: &'a mut GcReport,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let used = games
+                .iter()
+                .filter_map(|game| game.flatpak_app_id.clone())
+                .collect::<HashSet<String>>();
+            // A separate installation only has games in it, the regular one can have anything
+            let owned = match self.user_dir {
+                Some(_) => None,
+                None => {
+                    // Games installed before apps were recorded are only known from their
+                    // game.json, so their apps are recorded now
+                    let mut owned = installed_apps().await;
+                    let recorded = owned.len();
+                    owned.extend(used.iter().cloned());
+                    if owned.len() > recorded {
+                        write_installed_apps(&owned).await?;
+                    }
+                    Some(owned)
+                }
+            };
+            let user_dir = self.user_dir.clone();
+            let collected = tokio::task::spawn_blocking(move || {
+                let mut collected = GcReport::default();
+                uninstall_unused(user_dir.as_deref(), &used, owned.as_ref(), &mut collected)
+                    .map(|()| (collected, owned))
+            })
+            .await?;
+            let (collected, owned) = collected?;
+            if let Some(mut owned) = owned {
+                if !collected.apps_uninstalled.is_empty() {
+                    for app in &collected.apps_uninstalled {
+                        owned.remove(app);
+                    }
+                    write_installed_apps(&owned).await?;
+                }
+            }
+            report.apps_uninstalled.extend(collected.apps_uninstalled);
+            report
+                .runtimes_uninstalled
+                .extend(collected.runtimes_uninstalled);
+            report.bytes_reclaimed += collected.bytes_reclaimed;
+            Ok(())
+        })
+    }
+
+    fn verify<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        _game_dir: &'a Path,
+        bundle: Option<&'a Path>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let app_id = app_id(game)?.to_owned();
+            let user_dir = self.user_dir.clone();
+            let recorded = game.flatpak_commit.clone();
+            let bundle = bundle.map(PathBuf::from);
+            tokio::task::spawn_blocking(move || {
+                verify_app(
+                    user_dir.as_deref(),
+                    app_id.as_str(),
+                    recorded.as_deref(),
+                    bundle.as_deref(),
+                )
+            })
+            .await?
+        })
+    }
+
+    fn launch<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        game_dir: &'a Path,
+        args: &'a [String],
+        mut envs: HashMap<String, String>,
+        limits: &'a ResourceLimits,
+    ) -> BoxFuture<'a, Result<Child, Error>> {
+        Box::pin(async move {
+            // `flatpak run` needs to look in the same installation the game was installed into
+            if let Some(dir) = &self.user_dir {
+                envs.insert(String::from("FLATPAK_USER_DIR"), dir.clone());
+            }
+
+            let rules = policy::for_game(&policy::current()?, game.id.as_str());
+            // `flatpak run` finds the installation through FLATPAK_USER_DIR in `envs`
+            let mut command = limits::scoped_command("flatpak", limits);
+            command
+                .arg("run")
+                .arg("--user")
+                .args(&rules.launch_args)
+                .arg("--cwd=/app/publish")
+                .arg(app_id(game)?)
+                // Anything after the app ID is passed to the game, not read by flatpak
+                .args(args)
+                .current_dir(game_dir)
+                // Oops, there's kind of secrets in there
+                .env_clear()
+                .envs(envs)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            limits::set_rlimits(&mut command, limits);
+            Ok(command.spawn()?)
+        })
+    }
+
+    fn watch_limits(
+        &self,
+        session_id: u64,
+        game: DevcadeGame,
+        limits: ResourceLimits,
+    ) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            if let Some(app_id) = game.flatpak_app_id {
+                limits::apply(session_id, app_id, limits).await;
+            }
+        })
+    }
+
+    fn is_running<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        _pid: u32,
+    ) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move { Ok(!sandbox::instance_pids(app_id(game)?).await?.is_empty()) })
+    }
+
+    fn owns_process<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        _pid: u32,
+        process: i32,
+    ) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move {
+            Ok(sandbox::sandbox_pids(app_id(game)?)
+                .await?
+                .contains(&process))
+        })
+    }
+
+    fn terminate<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        _pid: u32,
+    ) -> BoxFuture<'a, Result<TerminationStage, Error>> {
+        Box::pin(async move { sandbox::terminate(self, app_id(game)?).await })
+    }
+
+    fn kill<'a>(&'a self, game: &'a DevcadeGame, _pid: u32) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move { self.kill_app(app_id(game)?).await })
+    }
+
+    fn freeze<'a>(&'a self, game: &'a DevcadeGame, _pid: u32) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move { sandbox::freeze(app_id(game)?).await })
+    }
+
+    fn thaw<'a>(&'a self, game: &'a DevcadeGame, _pid: u32) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move { sandbox::thaw(app_id(game)?).await })
+    }
+}
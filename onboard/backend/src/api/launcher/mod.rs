@@ -0,0 +1,164 @@
+use crate::config::ResourceLimits;
+use crate::env::{flatpak_user_dir, launcher};
+use anyhow::Error;
+use devcade_onboard_types::{schema::DevcadeGame, GcReport, TerminationStage};
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::process::Child;
+
+/**
+ * Launcher that installs games as flatpak apps and runs them in their sandbox
+ */
+mod flatpak;
+
+/**
+ * Launcher that runs an executable from the game's directory as a plain process
+ */
+mod native;
+
+pub use flatpak::{FlatpakLauncher, APPS_JSON};
+pub use native::NativeLauncher;
+
+/**
+ * Something that can install, launch and stop games. Which one is used is selected per API
+ * environment with `DEVCADE_LAUNCHER` and `DEVCADE_DEV_LAUNCHER`.
+ *
+ * Methods that act on a running game get the game and the PID of the process `launch` spawned.
+ */
+pub trait GameLauncher: Send + Sync {
+    /**
+     * Name of the launcher, as used in the environment and in logs
+     */
+    fn name(&self) -> &'static str;
+
+    /**
+     * Install a version of a game from its bundle, filling in anything the launcher needs later
+     * to launch it, like its flatpak app ID.
+     */
+    fn install<'a>(
+        &'a self,
+        game: &'a mut DevcadeGame,
+        bundle: &'a Path,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    /**
+     * Remove everything the launcher installed for a game
+     */
+    fn uninstall<'a>(&'a self, game: &'a DevcadeGame) -> BoxFuture<'a, Result<(), Error>>;
+
+    /**
+     * Remove whatever the launcher installed that none of `games` use anymore, and add it to
+     * `report`. `games` are every version of every game that is still installed.
+     */
+    fn collect_garbage<'a>(
+        &'a self,
+        _games: &'a [DevcadeGame],
+        _report: &'a mut GcReport,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /**
+     * Check that the installed version of a game can be launched. `bundle` is the retained bundle
+     * of that version, if there is one, which the install should match.
+     */
+    fn verify<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        game_dir: &'a Path,
+        bundle: Option<&'a Path>,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    /**
+     * Spawn a game with its stdout and stderr piped. `args` are passed to the game itself, and
+     * `envs` is the whole environment of the game.
+     */
+    fn launch<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        game_dir: &'a Path,
+        args: &'a [String],
+        envs: HashMap<String, String>,
+        limits: &'a ResourceLimits,
+    ) -> BoxFuture<'a, Result<Child, Error>>;
+
+    /**
+     * Enforce the limits that can only be applied once a game is running, and watch for the game
+     * running into them until its session ends.
+     */
+    fn watch_limits(
+        &self,
+        _session_id: u64,
+        _game: DevcadeGame,
+        _limits: ResourceLimits,
+    ) -> BoxFuture<'static, ()> {
+        Box::pin(async {})
+    }
+
+    /**
+     * Whether anything of a game is still running
+     */
+    fn is_running<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        pid: u32,
+    ) -> BoxFuture<'a, Result<bool, Error>>;
+
+    /**
+     * Whether `process` is one of the processes of a running game
+     */
+    fn owns_process<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        pid: u32,
+        process: i32,
+    ) -> BoxFuture<'a, Result<bool, Error>>;
+
+    /**
+     * Stop a game, giving it `terminate_grace_period` to exit by itself before escalating.
+     * Returns the stage that was needed.
+     */
+    fn terminate<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        pid: u32,
+    ) -> BoxFuture<'a, Result<TerminationStage, Error>>;
+
+    /**
+     * Immediately kill everything a game left running
+     */
+    fn kill<'a>(&'a self, game: &'a DevcadeGame, pid: u32) -> BoxFuture<'a, Result<(), Error>>;
+
+    /**
+     * Stop every process of a game without ending it
+     */
+    fn freeze<'a>(&'a self, game: &'a DevcadeGame, pid: u32) -> BoxFuture<'a, Result<(), Error>>;
+
+    /**
+     * Continue a game stopped with `freeze`
+     */
+    fn thaw<'a>(&'a self, game: &'a DevcadeGame, pid: u32) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+impl std::fmt::Debug for dyn GameLauncher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/**
+ * Get the launcher for the current API environment. The result should be kept for as long as it
+ * is used, so switching environments can't split e.g. an install between two launchers.
+ */
+#[must_use]
+pub fn current() -> Box<dyn GameLauncher> {
+    match launcher().as_str() {
+        "native" => Box::new(NativeLauncher),
+        "flatpak" => Box::new(FlatpakLauncher::new(flatpak_user_dir())),
+        other => {
+            log::warn!("Unknown launcher '{other}', falling back to flatpak");
+            Box::new(FlatpakLauncher::new(flatpak_user_dir()))
+        }
+    }
+}
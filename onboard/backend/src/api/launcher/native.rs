@@ -0,0 +1,204 @@
+use super::GameLauncher;
+use crate::api::{limits, sandbox};
+use crate::config::{self, ResourceLimits};
+use crate::env::terminate_grace_period;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::{schema::DevcadeGame, TerminationStage};
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+
+/**
+ * Executable run from the game's directory if the config doesn't name one
+ */
+const DEFAULT_EXECUTABLE: &str = "run";
+
+/**
+ * Runs an executable from the game's directory as a plain process, for developing on machines
+ * without flatpak. Games get their own process group so everything they start can be signalled
+ * together. Memory, CPU and process limits aren't enforced.
+ */
+pub struct NativeLauncher;
+
+/**
+ * Whether any process in a process group is still running
+ */
+fn group_alive(pgid: u32) -> bool {
+    // SAFETY: kill has no memory safety requirements, it only looks at its arguments
+    unsafe { libc::kill(-(pgid as i32), 0) == 0 }
+}
+
+fn signal_group(pgid: u32, signal: libc::c_int) {
+    sandbox::signal(&[-(pgid as i32)], signal);
+}
+
+/**
+ * Wait up to `timeout` for every process in a group to exit. Returns whether they did.
+ */
+async fn wait_for_group(pgid: u32, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while group_alive(pgid) {
+        if start.elapsed() >= timeout {
+            return false;
+        }
+        tokio::time::sleep(sandbox::POLL_INTERVAL).await;
+    }
+    true
+}
+
+/**
+ * Path of the executable that is run for a game
+ */
+fn executable(game: &DevcadeGame, game_dir: &Path) -> PathBuf {
+    let executable = config::load()
+        .games
+        .get(game.id.as_str())
+        .and_then(|game| game.executable.clone())
+        .unwrap_or_else(|| String::from(DEFAULT_EXECUTABLE));
+    game_dir.join(executable)
+}
+
+impl GameLauncher for NativeLauncher {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn install<'a>(
+        &'a self,
+        game: &'a mut DevcadeGame,
+        _bundle: &'a Path,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            // Bundles are flatpak bundles, which can't be run without flatpak. The bundle is still
+            // retained, and the executable is provided by the developer.
+            log::info!(
+                "Not installing the bundle of {}, the native launcher runs the game's executable",
+                game.id
+            );
+            Ok(())
+        })
+    }
+
+    fn uninstall<'a>(&'a self, _game: &'a DevcadeGame) -> BoxFuture<'a, Result<(), Error>> {
+        // Nothing is installed outside of the game's directory
+        Box::pin(async { Ok(()) })
+    }
+
+    fn verify<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        game_dir: &'a Path,
+        _bundle: Option<&'a Path>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let executable = executable(game, game_dir);
+            if !executable.is_file() {
+                return Err(anyhow!(
+                    "No executable for {} at {executable:?}, set one in the config",
+                    game.id
+                ));
+            }
+            Ok(())
+        })
+    }
+
+    fn launch<'a>(
+        &'a self,
+        game: &'a DevcadeGame,
+        game_dir: &'a Path,
+        args: &'a [String],
+        envs: HashMap<String, String>,
+        limits: &'a ResourceLimits,
+    ) -> BoxFuture<'a, Result<Child, Error>> {
+        Box::pin(async move {
+            self.verify(game, game_dir, None).await?;
+
+            let mut command = Command::new(executable(game, game_dir));
+            command
+                .args(args)
+                .current_dir(game_dir)
+                .env_clear()
+                .envs(envs)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            // SAFETY: the closure only calls setpgid, which is async-signal-safe
+            unsafe {
+                command.pre_exec(|| {
+                    if libc::setpgid(0, 0) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+            limits::set_rlimits(&mut command, limits);
+            Ok(command.spawn()?)
+        })
+    }
+
+    fn is_running<'a>(
+        &'a self,
+        _game: &'a DevcadeGame,
+        pid: u32,
+    ) -> BoxFuture<'a, Result<bool, Error>> {
+        Box::pin(async move { Ok(group_alive(pid)) })
+    }
+
+    fn owns_process<'a>(
+        &'a self,
+        _game: &'a DevcadeGame,
+        pid: u32,
+        process: i32,
+    ) -> BoxFuture<'a, Result<bool, Error>> {
+        // Everything the game starts stays in its process group
+        // SAFETY: getpgid has no memory safety requirements, it only looks at its argument
+        Box::pin(async move { Ok(unsafe { libc::getpgid(process) } == pid as i32) })
+    }
+
+    fn terminate<'a>(
+        &'a self,
+        _game: &'a DevcadeGame,
+        pid: u32,
+    ) -> BoxFuture<'a, Result<TerminationStage, Error>> {
+        Box::pin(async move {
+            if !group_alive(pid) {
+                return Ok(TerminationStage::AlreadyExited);
+            }
+            signal_group(pid, libc::SIGTERM);
+            if wait_for_group(pid, terminate_grace_period()).await {
+                return Ok(TerminationStage::Terminated);
+            }
+            log::warn!("Process group {pid} didn't exit within its grace period, sending SIGKILL");
+            signal_group(pid, libc::SIGKILL);
+            if wait_for_group(pid, sandbox::KILL_TIMEOUT).await {
+                return Ok(TerminationStage::Killed);
+            }
+            Err(anyhow!(
+                "Process group {pid} is still running after SIGKILL"
+            ))
+        })
+    }
+
+    fn kill<'a>(&'a self, _game: &'a DevcadeGame, pid: u32) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            signal_group(pid, libc::SIGKILL);
+            Ok(())
+        })
+    }
+
+    fn freeze<'a>(&'a self, _game: &'a DevcadeGame, pid: u32) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            signal_group(pid, libc::SIGSTOP);
+            Ok(())
+        })
+    }
+
+    fn thaw<'a>(&'a self, _game: &'a DevcadeGame, pid: u32) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            signal_group(pid, libc::SIGCONT);
+            Ok(())
+        })
+    }
+}
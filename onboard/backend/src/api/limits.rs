@@ -0,0 +1,219 @@
+use super::sandbox;
+use crate::config::ResourceLimits;
+use crate::session;
+use anyhow::{anyhow, Error};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+/**
+ * How long to wait for a game's sandbox to show up after launching it
+ */
+const SANDBOX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/**
+ * How often to check whether a game has run into one of its limits
+ */
+const MONITOR_INTERVAL: Duration = Duration::from_millis(500);
+
+/**
+ * Start of the name of each systemd scope a game is launched in
+ */
+const SCOPE_PREFIX: &str = "devcade-game-";
+
+/**
+ * Limit the number of open files of the process a command spawns. This is an rlimit, so it is
+ * inherited by everything the game starts. It can't be raised above the backend's own hard limit.
+ */
+pub fn set_rlimits(command: &mut Command, limits: &ResourceLimits) {
+    let Some(max_open_files) = limits.max_open_files else {
+        return;
+    };
+    // SAFETY: the closure only calls getrlimit and setrlimit, which are async-signal-safe
+    unsafe {
+        command.pre_exec(move || {
+            let mut limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let max = (max_open_files as libc::rlim_t).min(limit.rlim_max);
+            limit.rlim_cur = max;
+            limit.rlim_max = max;
+            if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/**
+ * Get the systemd properties that enforce the memory, CPU and process limits
+ */
+fn properties(limits: &ResourceLimits) -> Vec<String> {
+    let mut properties = Vec::new();
+    if let Some(memory_mb) = limits.memory_mb {
+        properties.push(format!("MemoryMax={memory_mb}M"));
+        properties.push(String::from("MemorySwapMax=0"));
+    }
+    if let Some(cpu_percent) = limits.cpu_percent {
+        properties.push(format!("CPUQuota={cpu_percent}%"));
+    }
+    if let Some(max_processes) = limits.max_processes {
+        properties.push(format!("TasksMax={max_processes}"));
+    }
+    properties
+}
+
+/**
+ * Build a command that runs `program` in a new systemd scope which has the memory, CPU and
+ * process limits from the start, so the game never runs without them. If none of those limits
+ * are set, `program` is run directly.
+ */
+pub fn scoped_command(program: &str, limits: &ResourceLimits) -> Command {
+    let properties = properties(limits);
+    if properties.is_empty() {
+        return Command::new(program);
+    }
+    static SCOPE_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut command = Command::new("systemd-run");
+    command
+        .arg("--user")
+        .arg("--scope")
+        .arg("--quiet")
+        .arg("--collect")
+        .arg(format!(
+            "--unit={SCOPE_PREFIX}{}-{}",
+            std::process::id(),
+            SCOPE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+    for property in properties {
+        command.arg(format!("--property={property}"));
+    }
+    command.arg("--").arg(program);
+    command
+}
+
+/**
+ * Make sure a game's sandbox has its memory, CPU and process limits, and watch it for as long as
+ * the session lasts so that running into a limit is reported as the reason the game crashed.
+ *
+ * Games are launched in a scope that already has the limits, see [`scoped_command`]. flatpak can
+ * still move its sandbox into a scope of its own though, in which case the limits are set on that
+ * scope as well.
+ */
+pub async fn apply(session_id: u64, app_id: String, limits: ResourceLimits) {
+    if limits.is_empty() {
+        return;
+    }
+    let cgroup = match sandbox_cgroup(app_id.as_str()).await {
+        Ok(cgroup) => cgroup,
+        Err(err) => {
+            log::warn!("Couldn't watch resource limits of {app_id}: {err}");
+            return;
+        }
+    };
+    let launched_in_scope = cgroup
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(SCOPE_PREFIX));
+    if !launched_in_scope {
+        log::debug!("{app_id} moved to its own scope, applying its limits there too");
+        if let Err(err) = set_properties(&cgroup, &limits).await {
+            log::warn!("Couldn't apply resource limits to {app_id}: {err}");
+        }
+    }
+    log::debug!("Watching {limits:?} of {app_id} ({cgroup:?})");
+    monitor(session_id, &cgroup).await;
+}
+
+/**
+ * Find the cgroup of a game's sandbox, relative to the cgroup filesystem root
+ */
+async fn sandbox_cgroup(app_id: &str) -> Result<PathBuf, Error> {
+    let start = Instant::now();
+    let pid = loop {
+        if let Some(pid) = sandbox::root_pids(app_id).await?.first() {
+            break *pid;
+        }
+        if start.elapsed() >= SANDBOX_TIMEOUT {
+            return Err(anyhow!("its sandbox never started"));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    // On the unified hierarchy, the only line is `0::<path>`
+    let cgroups = tokio::fs::read_to_string(format!("/proc/{pid}/cgroup")).await?;
+    cgroups
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("process {pid} isn't in a cgroup v2 hierarchy"))
+}
+
+/**
+ * Set the limits on the systemd scope a sandbox runs in
+ */
+async fn set_properties(cgroup: &Path, limits: &ResourceLimits) -> Result<(), Error> {
+    let unit = cgroup
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| name.ends_with(".scope"))
+        .ok_or_else(|| anyhow!("{cgroup:?} isn't a systemd scope"))?;
+
+    let properties = properties(limits);
+    if properties.is_empty() {
+        return Ok(());
+    }
+
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .arg("set-property")
+        .arg("--runtime")
+        .arg(unit)
+        .args(properties)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(anyhow!("systemctl set-property failed ({status})"));
+    }
+    Ok(())
+}
+
+/**
+ * Read a counter from one of a cgroup's `*.events` files
+ */
+async fn read_event(cgroup: &Path, file: &str, key: &str) -> Option<u64> {
+    let path = Path::new("/sys/fs/cgroup")
+        .join(cgroup.strip_prefix("/").unwrap_or(cgroup))
+        .join(file);
+    let events = tokio::fs::read_to_string(path).await.ok()?;
+    events.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.parse().ok())?
+    })
+}
+
+/**
+ * Watch a sandbox's cgroup until the session ends, reporting each limit the game runs into
+ */
+async fn monitor(session_id: u64, cgroup: &Path) {
+    let checks = [
+        ("memory.events", "oom_kill", "Ran out of memory"),
+        ("pids.events", "max", "Started too many processes"),
+    ];
+    let mut reported = [false; 2];
+    while session::current().is_some_and(|session| session.id == session_id) {
+        for ((file, key, reason), reported) in checks.iter().zip(reported.iter_mut()) {
+            if !*reported && read_event(cgroup, file, key).await > Some(0) {
+                log::warn!("Session {session_id}: {reason}");
+                session::limit_exceeded(session_id, reason);
+                *reported = true;
+            }
+        }
+        tokio::time::sleep(MONITOR_INTERVAL).await;
+    }
+}
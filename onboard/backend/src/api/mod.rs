@@ -1,25 +1,29 @@
-use crate::env::{api_url, devcade_path};
+use crate::config;
+use crate::env::{api_url, games_path, hide_broken_games, machine_name, player_count, production};
 use crate::nfc::NFC_CLIENT;
+use crate::policy;
+use crate::session;
 use anyhow::{anyhow, Error};
 use devcade_onboard_types::{
     schema::{DevcadeGame, MinimalGame, Tag, User},
-    Map, Player, Value,
+    AssetPrefetchResult, GameSessionInfo, GcReport, InstallReport, Map, Player, SandboxPolicy,
+    SessionState, TerminationStage, Value,
 };
+use launcher::GameLauncher;
 use log::{log, Level};
 
 use lazy_static::lazy_static;
 use libflatpak::{gio, prelude::*, Installation, Transaction};
 use std::collections::{HashMap, HashSet};
-use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 use tokio::sync::oneshot;
 
 lazy_static! {
-    static ref CURRENT_GAME: Mutex<Option<DevcadeGame>> =
-        Mutex::new(None);
     // basically just checks if a user 'devcade' exists. If so, assumes that this is running on the
     // machine, and saves to the homedir. Otherwise, saves to the cwd.
     static ref ON_MACHINE: bool = Path::new("/home/devcade").exists();
@@ -34,7 +38,9 @@ mod network {
     use anyhow::Error;
     use lazy_static::lazy_static;
     use log::{log, Level};
-    use serde::Deserialize;
+    use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+    use reqwest::StatusCode;
+    use serde::{Deserialize, Serialize};
     use std::ops::Deref;
 
     // Construct a static client to be used for all requests. Prevents opening a new connection for
@@ -68,8 +74,92 @@ mod network {
         let bytes = response.bytes().await?;
         Ok(bytes.to_vec())
     }
+
+    /**
+     * HTTP cache validators the server sent along with a downloaded file
+     */
+    #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+    pub struct Validators {
+        pub etag: Option<String>,
+        pub last_modified: Option<String>,
+    }
+
+    /**
+     * Request binary data from a URL, unless the server reports that it hasn't changed since it
+     * was downloaded with the given validators. Returns `None` if the data is unchanged.
+     *
+     * # Errors
+     * This function will return an error if the request fails or the server returns an error
+     * status.
+     */
+    pub async fn request_bytes_if_modified(
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Option<(Vec<u8>, Validators)>, Error> {
+        log!(Level::Trace, "Conditionally requesting binary from {}", url);
+        let mut request = CLIENT.deref().get(url);
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        let validators = Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        let bytes = response.bytes().await?;
+        Ok(Some((bytes.to_vec(), validators)))
+    }
 }
 
+/**
+ * Module for keeping game icons and banners up to date with the API
+ */
+mod assets;
+
+/**
+ * Module for installing and rolling back versions of games
+ */
+mod install;
+
+/**
+ * Module for checking that what is installed is consistent with itself and the catalog
+ */
+mod audit;
+
+/**
+ * Module for deleting bundles, apps and runtimes that aren't needed anymore
+ */
+mod gc;
+
+/**
+ * Module for signalling and stopping the processes in a game's sandbox
+ */
+mod sandbox;
+
+/**
+ * Module for limiting the memory, CPU and processes a game can use
+ */
+mod limits;
+
+/**
+ * Module for the different ways games can be installed and run
+ */
+pub mod launcher;
+
 /**
  * Internal module for API routes and URLs
  * This is used to make sure that the API routes are consistent across the codebase, and can be
@@ -153,6 +243,7 @@ pub async fn game_list() -> Result<Vec<DevcadeGame>, Error> {
     Ok(games
         .into_iter()
         .filter(|game| game.hash.is_some())
+        .filter_map(with_local_state)
         .collect::<Vec<DevcadeGame>>())
 }
 
@@ -176,87 +267,74 @@ pub async fn get_game(id: &str) -> Result<DevcadeGame, Error> {
  */
 pub fn game_list_from_fs() -> Result<Vec<DevcadeGame>, Error> {
     let mut games = Vec::new();
-    for entry in std::fs::read_dir(devcade_path())? {
+    let games_path = games_path();
+    if !Path::new(games_path.as_str()).exists() {
+        // Nothing has been installed for this API environment yet
+        return Ok(games);
+    }
+    for entry in std::fs::read_dir(games_path)? {
         let entry = entry?;
         let path = entry.path();
         if !path.is_dir() {
             continue;
         }
 
-        for entry_ in std::fs::read_dir(path)? {
-            let entry_ = entry_?;
-            let path_ = entry_.path();
-            if path_.is_dir() {
-                continue;
-            }
-
-            if let Ok(game) = game_from_path(&path_) {
-                games.push(game);
-            }
+        // Only the installed version counts, not e.g. the one retained for rollbacks
+        if let Ok(game) = game_from_path(&path.join(install::GAME_JSON)) {
+            games.extend(with_local_state(game));
         }
     }
     Ok(games)
 }
 
 /**
- * Download's a game's banner from the API.
+ * Download's a game's banner from the API. If the banner was already downloaded, it is only
+ * downloaded again if the game or the banner changed.
  *
  * # Errors
- * This function will return an error if the request fails, or if the filesystem cannot be written to.
+ * This function will return an error if the request fails, the response isn't an image, or if
+ * the filesystem cannot be written to.
  */
 pub async fn download_banner(game_id: String) -> Result<(), Error> {
-    let path = Path::new(devcade_path().as_str())
-        .join(game_id.clone())
-        .join("banner.png");
-    if path.exists() {
-        return Ok(());
-    }
-    if !path.parent().unwrap().exists() {
-        std::fs::create_dir_all(path.parent().unwrap())?;
-    }
-
-    let bytes = network::request_bytes(
-        format!("{}/{}", api_url(), route::game_banner(game_id.as_str())).as_str(),
-    )
-    .await?;
-    std::fs::write(path, bytes)?;
-    Ok(())
+    assets::download(game_id.as_str(), assets::Asset::Banner).await
 }
 
 /**
- * Download's a game's icon from the API.
+ * Download's a game's icon from the API. If the icon was already downloaded, it is only
+ * downloaded again if the game or the icon changed.
  *
  * # Errors
- * This function will return an error if the request fails, or if the filesystem cannot be written to.
+ * This function will return an error if the request fails, the response isn't an image, or if
+ * the filesystem cannot be written to.
  */
 pub async fn download_icon(game_id: String) -> Result<(), Error> {
-    let api_url = api_url();
-    let file_path = devcade_path();
-
-    let path = Path::new(file_path.as_str())
-        .join(game_id.clone())
-        .join("icon.png");
-    if path.exists() {
-        return Ok(());
-    }
-    if !path.parent().unwrap().exists() {
-        std::fs::create_dir_all(path.parent().unwrap())?;
-    }
+    assets::download(game_id.as_str(), assets::Asset::Icon).await
+}
 
-    let bytes = network::request_bytes(
-        format!("{}/{}", api_url, route::game_icon(game_id.as_str())).as_str(),
-    )
-    .await?;
-    std::fs::write(path, bytes)?;
-    Ok(())
+/**
+ * Download the icons and banners of several games concurrently. If `game_ids` is `None`, the
+ * artwork for every game in the game list is downloaded.
+ *
+ * # Errors
+ * This function will return an error if the game list cannot be fetched. Failures for individual
+ * games are reported in the returned results.
+ */
+pub async fn prefetch_assets(
+    game_ids: Option<Vec<String>>,
+) -> Result<Vec<AssetPrefetchResult>, Error> {
+    assets::prefetch(game_ids).await
 }
 
 pub async fn nfc_tags(reader_id: Player) -> Result<Option<String>, Error> {
     assert!(reader_id == Player::P1);
-    NFC_CLIENT
+    let handle = NFC_CLIENT
         .submit()
         .await
-        .map_err(|err| anyhow!("Couldn't get NFC tags: {:?}", err))
+        .map_err(|err| anyhow!("Couldn't get NFC tags: {:?}", err))?;
+    if let Some(handle) = handle.as_ref() {
+        session::signed_in(reader_id, handle.clone());
+    }
+    Ok(handle)
 }
 
 pub async fn nfc_user(association_id: String) -> Result<Map<String, Value>, Error> {
@@ -266,11 +344,40 @@ pub async fn nfc_user(association_id: String) -> Result<Map<String, Value>, Erro
         .map_err(|err| anyhow!("Couldn't get NFC user: {:?}", err))
 }
 
-async fn install_flatpak_bundle_async(bundle_path: PathBuf) -> Result<String, Error> {
+/**
+ * Open the flatpak installation games are installed into. `user_dir` should come from
+ * `flatpak_user_dir`, and selects a separate user installation for development games.
+ *
+ * # Errors
+ * This function will return an error if the installation cannot be opened or created.
+ */
+pub(crate) fn flatpak_installation(user_dir: Option<&str>) -> Result<Installation, Error> {
+    match user_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            Ok(Installation::new_for_path(
+                &gio::File::for_path(dir),
+                true,
+                None::<&gio::Cancellable>,
+            )?)
+        }
+        None => Ok(Installation::new_user(None::<&gio::Cancellable>)?),
+    }
+}
+
+async fn install_flatpak_bundle_async(
+    bundle_path: PathBuf,
+    user_dir: Option<String>,
+    rules: SandboxPolicy,
+) -> Result<String, Error> {
     let (tx, rx) = oneshot::channel();
     std::thread::spawn(move || {
-        tx.send(install_flatpak_bundle(&bundle_path))
-            .expect("Server thread died before we could send flatpak install response?")
+        tx.send(install_flatpak_bundle(
+            &bundle_path,
+            user_dir.as_deref(),
+            rules,
+        ))
+        .expect("Server thread died before we could send flatpak install response?")
     });
     match rx.await {
         Ok(result) => result,
@@ -278,11 +385,17 @@ async fn install_flatpak_bundle_async(bundle_path: PathBuf) -> Result<String, Er
     }
 }
 
-fn install_flatpak_bundle(bundle_path: &Path) -> Result<String, Error> {
-    let transaction = Transaction::for_installation(
-        &Installation::new_user(None::<&gio::Cancellable>)?,
-        None::<&gio::Cancellable>,
-    )?;
+/**
+ * Install a flatpak bundle, refusing it if it asks for permissions `rules` doesn't allow. `rules`
+ * should be the policy for the game being installed, from `policy::for_game`.
+ */
+fn install_flatpak_bundle(
+    bundle_path: &Path,
+    user_dir: Option<&str>,
+    rules: SandboxPolicy,
+) -> Result<String, Error> {
+    let transaction =
+        Transaction::for_installation(&flatpak_installation(user_dir)?, None::<&gio::Cancellable>)?;
     transaction.set_no_pull(false);
     transaction.set_no_interaction(true);
     transaction.add_default_dependency_sources();
@@ -307,7 +420,7 @@ fn install_flatpak_bundle(bundle_path: &Path) -> Result<String, Error> {
                     app_name = Some(name.clone());
                 }
                 log::debug!("Name of bundle is {name:?}");
-                match is_install_allowed(&metadata) {
+                match is_install_allowed(&metadata, &rules) {
                     Ok(true) => {
                         log::debug!("All permissions look OK on app {name:?}");
                     }
@@ -333,45 +446,33 @@ fn install_flatpak_bundle(bundle_path: &Path) -> Result<String, Error> {
     //Ok("todo".to_owned())
 }
 
-fn is_install_allowed(metadata: &gio::glib::KeyFile) -> Result<bool, Error> {
-    if !metadata.has_group("Context") {
-        return Ok(true);
-    }
-    let allowed_permissions = HashMap::from([
-        ("shared", HashSet::from(["network", "ipc"])),
-        ("sockets", HashSet::from(["x11", "pulseaudio"])),
-        ("devices", HashSet::from(["dri", "input"])),
-        (
-            "filesystems",
-            HashSet::from(["/tmp/devcade/persistence.sock", "/tmp/devcade/game.sock"]),
-        ),
-    ]);
-
-    for (realm, allowed_capabilities) in allowed_permissions.iter() {
-        if !metadata.has_key("Context", realm)? {
-            continue;
-        }
-        for capability in metadata
-            .string_list("Context", realm)?
-            .iter()
-            .map(|entry| entry.to_str())
-        {
-            if !allowed_capabilities.contains(capability) {
-                // Disallowed/unknown cap!
-                log::error!("Unknown capability {realm}={capability} is not allowed!");
-                return Ok(false);
-            }
-        }
+fn is_install_allowed(metadata: &gio::glib::KeyFile, rules: &SandboxPolicy) -> Result<bool, Error> {
+    let violations = policy::violations(rules, policy::context(metadata)?);
+    for violation in &violations {
+        log::error!("{violation}!");
     }
+    Ok(violations.is_empty())
+}
 
-    for realm in metadata.keys("Context")?.iter().map(|entry| entry.to_str()) {
-        if !allowed_permissions.contains_key(realm) {
-            log::error!("Unknown realm {realm} is not allowed!");
-            return Ok(false);
-        }
+/**
+ * Check that a game ID can be used as the name of a directory, so an ID from a request can't
+ * point anywhere outside the directories the backend keeps for that game.
+ *
+ * # Errors
+ * This function will return an error if the ID is empty, is `.` or `..`, or contains a path
+ * separator or NUL.
+ */
+pub fn validate_game_id(game_id: &str) -> Result<(), Error> {
+    let mut components = Path::new(game_id).components();
+    let single_name = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(name)), None) if name == game_id
+    );
+    if single_name && !game_id.contains(['\\', '\0']) {
+        Ok(())
+    } else {
+        Err(anyhow!("'{game_id}' isn't a valid game ID"))
     }
-
-    Ok(true)
 }
 
 /**
@@ -384,11 +485,15 @@ fn is_install_allowed(metadata: &gio::glib::KeyFile) -> Result<bool, Error> {
  */
 pub async fn download_game(game_id: String) -> Result<DevcadeGame, Error> {
     log::debug!("Downloading a game!");
-    let game_dir = Path::new(devcade_path().as_str()).join(game_id.clone());
-    let game_json_path = game_dir.join("game.json");
+    validate_game_id(game_id.as_str())?;
+    // Resolve the environment up front so that switching APIs mid-download can't split the
+    // install between the production and development directories
+    let game_dir = Path::new(games_path().as_str()).join(game_id.clone());
+    let launcher = launcher::current();
+    let game_json_path = game_dir.join(install::GAME_JSON);
 
     let local_game = game_from_path(&game_json_path);
-    let mut game = match get_game(game_id.as_str()).await {
+    let game = match get_game(game_id.as_str()).await {
         Ok(game) => {
             log::debug!("Fetched game meta!");
             game
@@ -397,58 +502,165 @@ pub async fn download_game(game_id: String) -> Result<DevcadeGame, Error> {
             log::warn!("Couldn't request live info on game! Falling back to local file! {err:?}");
             local_game
                 .as_ref()
-                .expect("Game not downloaded and we're offline!")
+                .map_err(|_| {
+                    anyhow!("Game {game_id} is not downloaded and the API can't be reached")
+                })?
                 .clone()
         }
     };
     // Is the current hash == the remote hash?
-    if let Ok(local_game) = local_game {
+    if let Ok(local_game) = &local_game {
         if local_game.hash == game.hash {
-            return Ok(local_game);
+            return Ok(local_game.clone());
         }
     }
 
+    if local_game.is_ok() && install::is_rolled_back(&game_dir, &game.hash).await {
+        log::info!(
+            "Not updating {} to version {:?}, it was rolled back from",
+            game.name,
+            game.hash
+        );
+        return local_game;
+    }
+
+    fetch_and_install(&game_dir, game, launcher.as_ref()).await
+}
+
+/**
+ * Download the bundle of a version of a game and install it, whatever is installed already.
+ *
+ * # Errors
+ * This function will return an error if the request fails, or if the bundle cannot be installed.
+ */
+async fn fetch_and_install(
+    game_dir: &Path,
+    game: DevcadeGame,
+    launcher: &dyn GameLauncher,
+) -> Result<DevcadeGame, Error> {
     log!(Level::Info, "Downloading game {}...", game.name);
 
     let bytes = network::request_bytes(
-        format!("{}/{}", api_url(), route::game_download(game_id.as_str())).as_str(),
+        format!("{}/{}", api_url(), route::game_download(game.id.as_str())).as_str(),
     )
     .await?;
 
-    log!(Level::Info, " game {}...", game.name);
-    log!(Level::Trace, "Flatpak bundle size: {} bytes", bytes.len());
+    log!(Level::Info, "Installing game {}...", game.name);
+    log!(Level::Trace, "Bundle size: {} bytes", bytes.len());
 
-    // // install flatpak
-    tokio::fs::create_dir_all(&game_dir).await?;
-    let bundle_path = game_dir.join("bundle.flatpak").to_owned();
-    tokio::fs::write(&bundle_path, &bytes).await?;
+    let game = install::install(game_dir, game, &bytes, launcher).await?;
+    log::debug!("Downloaded game {game:?}");
 
-    game.flatpak_app_id = Some(install_flatpak_bundle_async(bundle_path).await?);
-    log::info!("Hi, flatpak app id {:?}", game.flatpak_app_id);
+    Ok(game)
+}
 
-    // Write the game's JSON file to the game's directory (this is used later to get the games from
-    // the filesystem)
-    log!(
-        Level::Debug,
-        "Writing game.json file for game {}...",
-        game.name
-    );
-    log!(
-        Level::Trace,
-        "Game json path: {}",
-        game_json_path.to_str().unwrap()
-    );
-    let json = serde_json::to_string(&game)?;
-    match tokio::fs::write(&game_json_path, json).await {
-        Ok(_) => {}
-        Err(e) => {
-            log!(Level::Warn, "Error writing game.json file: {}", e);
-            return Err(e.into());
-        }
+/**
+ * Get a game ready to launch: download it if it isn't installed or is out of date, and check that
+ * the install is intact. A damaged install is repaired from its retained bundle, or downloaded
+ * again if that isn't possible.
+ *
+ * # Errors
+ * This function will return an error if the game can't be downloaded, or if it is still damaged
+ * after downloading it again.
+ */
+async fn prepare_game(
+    game_id: &str,
+    game_dir: &Path,
+    launcher: &dyn GameLauncher,
+) -> Result<DevcadeGame, Error> {
+    let game = download_game(game_id.to_owned()).await?;
+    let err = match install::verify(game_dir, game, launcher).await {
+        Ok(game) => return Ok(game),
+        Err(err) => err,
     };
-    log::debug!("Downloaded game {game:?}");
 
-    Ok(game)
+    log::warn!("Couldn't repair {game_id} locally, downloading it again: {err}");
+    let game = get_game(game_id)
+        .await
+        .map_err(|err| anyhow!("Game {game_id} is damaged and can't be downloaded again: {err}"))?;
+    let game = fetch_and_install(game_dir, game, launcher).await?;
+    install::verify(game_dir, game, launcher)
+        .await
+        .map_err(|err| anyhow!("Game {game_id} is still damaged after downloading it again: {err}"))
+}
+
+/**
+ * Reinstall the version of a game that was installed before its current version.
+ *
+ * # Errors
+ * This function will return an error if the game is running, there is no previous version, or if
+ * it cannot be installed.
+ */
+pub async fn rollback_game(game_id: String) -> Result<DevcadeGame, Error> {
+    validate_game_id(game_id.as_str())?;
+    install::rollback(game_id.as_str()).await
+}
+
+/**
+ * Move games and backend files from where older versions of the backend kept them.
+ *
+ * # Errors
+ * This function will return an error if the devcade directory can't be read, or something can't
+ * be moved.
+ */
+pub async fn migrate_layout() -> Result<(), Error> {
+    install::migrate_layout().await
+}
+
+/**
+ * Check every installed game for missing, orphaned, outdated and corrupt installs, and repair
+ * them if `repair` is set.
+ *
+ * # Errors
+ * This function will return an error if the games directory cannot be read.
+ */
+pub async fn verify_installations(repair: bool) -> Result<InstallReport, Error> {
+    audit::verify_installations(repair).await
+}
+
+/**
+ * Delete bundles, flatpak apps and runtimes that no installed game needs anymore.
+ *
+ * # Errors
+ * This function will return an error if the games directory cannot be read, or if something
+ * cannot be uninstalled.
+ */
+pub async fn collect_garbage() -> Result<GcReport, Error> {
+    gc::collect_garbage().await
+}
+
+/**
+ * Write a file by writing to a temporary file next to it and renaming that into place, so anyone
+ * reading the file never sees it half written.
+ *
+ * # Errors
+ * This function will return an error if the filesystem cannot be written to.
+ */
+pub(crate) async fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Can't write to {path:?}, it has no file name"))?;
+    // Unique per write, so two writes of the same file can't write into each other's temporary
+    // file and rename it into place half written
+    static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let tmp_path = path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let write = async {
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(contents).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp_path, path).await
+    };
+    if let Err(err) = write.await {
+        let _ = fs::remove_file(&tmp_path).await;
+        return Err(err.into());
+    }
+    Ok(())
 }
 
 fn generate_clean_env() -> HashMap<String, String> {
@@ -471,66 +683,150 @@ fn generate_clean_env() -> HashMap<String, String> {
 }
 
 /**
- * Launch a game by its ID. This will check if the game is downloaded, and if it is, it will launch
- * the game. This returns a `JoinHandle`, which should be used to check for game exit and notify the
- * backend.
+ * Get the variables the backend tells every game about the cabinet and its session. These are set
+ * after the game's own variables, so games can't change them.
+ */
+fn session_env(session: &session::Session) -> HashMap<String, String> {
+    HashMap::from([
+        (String::from("DEVCADE_GAME_ID"), session.game_id.clone()),
+        (String::from("DEVCADE_SESSION_ID"), session.id.to_string()),
+        (String::from("DEVCADE_SESSION_TOKEN"), session.token.clone()),
+        (
+            String::from("DEVCADE_PLAYER_COUNT"),
+            player_count().to_string(),
+        ),
+        (String::from("DEVCADE_MACHINE_NAME"), machine_name()),
+        (
+            String::from("DEVCADE_DEMO_MODE"),
+            String::from(if session.demo { "1" } else { "0" }),
+        ),
+    ])
+}
+
+/**
+ * Launch a game by its ID. This will download the game if it isn't downloaded yet, spawn it, and
+ * return the ID of the new session as soon as the game's process has been spawned. The session's
+ * lifecycle (running, exited, crashed) is reported through `crate::events`.
  *
  * # Errors
- * This function will return an error if the filesystem cannot be read from,
- * or if the game cannot be launched.
+ * This function will return an error if another game is running, if the game cannot be
+ * downloaded, or if the game cannot be launched.
+ */
+pub async fn launch_game(game_id: String) -> Result<u64, Error> {
+    launch(game_id, false).await
+}
+
+/**
+ * Launch a game as a demo for attract mode. The game is told it is being shown as a demo with
+ * `DEVCADE_DEMO_MODE=1`, and the session doesn't count towards its play statistics.
  *
- * # Panics
- * This function will never panic, but contains an `unwrap` call that will never fail. This section
- * is here to make clippy happy.
+ * # Errors
+ * This function will return an error if another game is running, if the game cannot be
+ * downloaded, or if the game cannot be launched.
+ */
+pub async fn launch_demo(game_id: String) -> Result<u64, Error> {
+    launch(game_id, true).await
+}
+
+async fn launch(game_id: String, demo: bool) -> Result<u64, Error> {
+    validate_game_id(game_id.as_str())?;
+    let session_id = session::begin(game_id.as_str(), demo, launcher::current().into())?;
+    match spawn_game(game_id, session_id).await {
+        Ok(()) => Ok(session_id),
+        Err(err) => {
+            session::launch_failed(session_id, &err).await;
+            Err(err)
+        }
+    }
+}
+
+/**
+ * Download and spawn the game for a session, and watch it in the background until it exits.
  */
-pub async fn launch_game(game_id: String) -> Result<(), Error> {
-    let path = Path::new(devcade_path().as_str())
-        .join(game_id.clone())
-        .join("publish");
+async fn spawn_game(game_id: String, session_id: u64) -> Result<(), Error> {
+    let session = session::current()
+        .filter(|session| session.id == session_id)
+        .ok_or_else(|| anyhow!("Session {session_id} ended before {game_id} was launched"))?;
+    let game_dir = Path::new(games_path().as_str()).join(game_id.clone());
 
     log!(Level::Info, "Launching game {}...", game_id);
-    log!(Level::Trace, "Game path: {}", path.to_str().unwrap());
+    log!(Level::Trace, "Game path: {:?}", game_dir);
 
-    // Downloads game if we don't already have it
-    let game = download_game(game_id.clone()).await?;
+    let launcher = session.launcher.clone();
+
+    // Downloads game if we don't already have it, and repairs it if it's damaged
+    let game = prepare_game(game_id.as_str(), &game_dir, launcher.as_ref()).await?;
 
     // flush data every time a new game is opened (in case previous launched game forgor)
     match persistence_flush().await {
         Ok(_) => {}
         Err(e) => log::warn!("Failed to flush save cache: {e}"),
     }
-    *CURRENT_GAME.lock().unwrap() = Some(game.clone());
 
-    let envs = generate_clean_env();
-    log!(Level::Trace, "Game ENV: {:?}", envs);
+    let config = config::load();
+    let rules = policy::for_game(&config.policy, game_id.as_str());
+    let options = policy::launch_options(&rules, game_id.as_str(), config.launch_for(&game));
 
-    // Launch the game and silence stdout (allow the game to print to stderr)
-    let mut child = Command::new("flatpak")
-        .arg("run")
-        .arg("--user")
-        .arg("--device=dri")
-        .arg("--socket=wayland")
-        .arg("--socket=x11")
-        .arg("--socket=fallback-x11")
-        .arg("--cwd=/app/publish")
-        .arg(game.flatpak_app_id.clone().unwrap())
-        // This unwrap is safe because it is guaranteed to have a parent
-        .current_dir(path.parent().unwrap())
-        // Oops, there's kind of secrets in there
-        .env_clear()
-        .envs(envs)
-        .spawn()
-        .expect("Failed to launch game");
+    let mut envs = generate_clean_env();
+    envs.extend(options.env);
+    envs.extend(session_env(&session));
+    // The token is what lets the game use the game socket, so it stays out of the logs
+    log!(
+        Level::Trace,
+        "Game ENV: {:?}",
+        envs.iter()
+            .filter(|(key, _)| *key != "DEVCADE_SESSION_TOKEN")
+            .collect::<HashMap<_, _>>()
+    );
+    log!(Level::Trace, "Game arguments: {:?}", options.args);
 
-    let wait_result = child.wait().await;
-    *CURRENT_GAME.lock().unwrap() = None;
-    wait_result.expect("Failed to launch game");
+    let limits = config.limits_for(game_id.as_str());
 
-    log::info!("Game finished!");
+    let mut child = launcher
+        .launch(&game, &game_dir, &options.args, envs, &limits)
+        .await
+        .map_err(|err| anyhow!("Failed to launch game {game_id}: {err}"))?;
+    // The child hasn't been waited on yet, so it still has its PID
+    let pid = child
+        .id()
+        .ok_or_else(|| anyhow!("Game {game_id} has no process ID"))?;
+    log::debug!("Launched {game_id} with the {} launcher", launcher.name());
+
+    let output =
+        session::logs::capture(&game, session_id, child.stdout.take(), child.stderr.take()).await;
+
+    session::running(session_id, game.clone(), pid);
+    tokio::spawn(launcher.watch_limits(session_id, game.clone(), limits));
+
+    tokio::spawn(async move {
+        let status = child.wait().await;
+        // Give the log a moment to catch up, but don't wait on anything the game left running
+        // with its output still open; that gets killed below.
+        if tokio::time::timeout(Duration::from_secs(2), output)
+            .await
+            .is_err()
+        {
+            log::debug!("Game output is still open after it exited");
+        }
+        match status {
+            Ok(status) => session::exited(session_id, status).await,
+            Err(err) => session::launch_failed(session_id, &err.into()).await,
+        }
 
-    tokio::time::sleep(Duration::from_millis(200)).await;
+        // Write out whatever the game saved before it exited
+        if let Err(err) = persistence_flush().await {
+            log::warn!("Failed to flush save cache: {err}");
+        }
 
-    kill_game(game).await?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Clean up anything the game left running in its sandbox
+        if launcher.is_running(&game, pid).await.unwrap_or(true) {
+            if let Err(err) = launcher.kill(&game, pid).await {
+                log::warn!("Failed to clean up after game: {err}");
+            }
+        }
+    });
 
     Ok(())
 }
@@ -576,7 +872,7 @@ pub async fn tag_games(name: String) -> Result<Vec<DevcadeGame>, Error> {
         .into_iter()
         .filter_map(|g| {
             if let Ok(g) = g {
-                Some(g)
+                with_local_state(g)
             } else {
                 log!(
                     Level::Warn,
@@ -622,6 +918,21 @@ fn game_from_path(path: &Path) -> Result<DevcadeGame, Error> {
     Ok(game)
 }
 
+/**
+ * Fill in what the backend knows about a game locally: the paths of the artwork that has been
+ * downloaded for it, and whether it keeps crashing. Returns `None` if the game is broken and
+ * broken games are hidden.
+ */
+fn with_local_state(mut game: DevcadeGame) -> Option<DevcadeGame> {
+    game.artwork = Some(Box::new(assets::artwork(game.id.as_str())));
+    game.broken = session::crash::is_broken(&game);
+    if game.broken && hide_broken_games() {
+        log::debug!("Hiding broken game {}", game.id);
+        return None;
+    }
+    Some(game)
+}
+
 async fn game_from_minimal(game: MinimalGame) -> Result<DevcadeGame, Error> {
     network::request_json::<DevcadeGame>(
         format!("{}/{}", api_url(), route::game(game.id.as_str())).as_str(),
@@ -630,26 +941,150 @@ async fn game_from_minimal(game: MinimalGame) -> Result<DevcadeGame, Error> {
 }
 
 pub fn current_game() -> Option<DevcadeGame> {
-    CURRENT_GAME.lock().unwrap().clone()
+    session::current_game()
 }
 
-async fn kill_game(game: DevcadeGame) -> Result<(), anyhow::Error> {
-    Command::new("flatpak")
-        .arg("kill")
-        .arg(game.flatpak_app_id.clone().unwrap())
-        .spawn()?
-        .wait()
-        .await?;
-    Ok(())
+/**
+ * Get the current session, its game and the PID the game was launched with.
+ *
+ * # Errors
+ * This function will return an error if no game is running, or if it is still being launched.
+ */
+fn running_session() -> Result<(session::Session, DevcadeGame, u32), Error> {
+    let session =
+        session::current().ok_or_else(|| anyhow!("There isn't a game running right now"))?;
+    match (session.game.clone(), session.pid) {
+        (Some(game), Some(pid)) => Ok((session, game, pid)),
+        _ => Err(anyhow!("Game {} is still being launched", session.game_id)),
+    }
 }
 
-pub async fn kill_current_game() -> Result<(), anyhow::Error> {
-    if let Some(current_game) = current_game() {
-        kill_game(current_game).await?;
+/**
+ * Check that a process belongs to the game that is currently running, so it may talk to the
+ * backend on the game's behalf.
+ *
+ * # Errors
+ * This function will return an error if no game is running, or if the process isn't part of it.
+ */
+pub async fn check_game_process(process: i32) -> Result<(), Error> {
+    let (session, game, pid) = running_session()?;
+    if session.launcher.owns_process(&game, pid, process).await? {
         Ok(())
     } else {
-        Err(anyhow!("Tried to kill game, but there wasn't one running!"))
+        Err(anyhow!(
+            "process {process} isn't part of game {}",
+            session.game_id
+        ))
+    }
+}
+
+/**
+ * Freeze every process of the game that is currently running, so it doesn't advance while the
+ * frontend shows something over it.
+ *
+ * # Errors
+ * This function will return an error if no game is running, or if its sandbox can't be found.
+ */
+pub async fn pause_game() -> Result<(), Error> {
+    let (session, game, pid) = running_session()?;
+    if session.state == SessionState::Paused {
+        return Ok(());
+    }
+    session.launcher.freeze(&game, pid).await?;
+    session::set_paused(session.id, true);
+    log::info!("Paused session {}", session.id);
+    Ok(())
+}
+
+/**
+ * Continue the game that was paused with [`pause_game`].
+ *
+ * # Errors
+ * This function will return an error if no game is running, or if its sandbox can't be found.
+ */
+pub async fn resume_game() -> Result<(), Error> {
+    let (session, game, pid) = running_session()?;
+    if session.state != SessionState::Paused {
+        return Err(anyhow!("Session {} isn't paused", session.id));
+    }
+    session.launcher.thaw(&game, pid).await?;
+    session::set_paused(session.id, false);
+    log::info!("Resumed session {}", session.id);
+    Ok(())
+}
+
+/**
+ * Stop the game that is currently running, giving it a chance to save and exit by itself first.
+ * Returns how far the termination had to escalate.
+ *
+ * # Errors
+ * This function will return an error if no game is running, or if its sandbox is still there
+ * after `flatpak kill`.
+ */
+pub async fn kill_current_game() -> Result<TerminationStage, anyhow::Error> {
+    let (session, game, pid) = running_session()?;
+    let launcher = session.launcher.clone();
+    session::stopping(session.id);
+    if session.state == SessionState::Paused {
+        // A stopped process can't handle SIGTERM
+        launcher.thaw(&game, pid).await?;
     }
+    let stage = launcher.terminate(&game, pid).await?;
+    log::info!("Stopped session {} ({stage:?})", session.id);
+    Ok(stage)
+}
+
+/**
+ * Get the volume of the cabinet in percent, or `None` if it can't be read
+ */
+async fn volume() -> Option<u8> {
+    let output = Command::new("pamixer")
+        .arg("--get-volume")
+        .output()
+        .await
+        .map_err(|err| log::warn!("Couldn't run pamixer: {err}"))
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/**
+ * Get what the game that is currently running can know about its session.
+ *
+ * # Errors
+ * This function will return an error if no game is running, or if it is still being launched.
+ */
+pub async fn session_info() -> Result<GameSessionInfo, Error> {
+    let (session, game, _) = running_session()?;
+    Ok(GameSessionInfo {
+        game_id: session.game_id,
+        game_hash: game.hash,
+        session_id: session.id,
+        players: session.players,
+        machine_name: machine_name(),
+        demo: session.demo,
+        volume: volume().await,
+    })
+}
+
+/**
+ * Stop the game that is currently running because it asked to go back to the menu. The game is
+ * stopped in the background, so it can still be answered before it is terminated.
+ *
+ * # Errors
+ * This function will return an error if no game is running, or if it is still being launched.
+ */
+pub fn exit_to_menu() -> Result<(), Error> {
+    let (session, _, _) = running_session()?;
+    if !session::exit_requested(session.id) {
+        // Already on its way out
+        return Ok(());
+    }
+    tokio::spawn(async move {
+        if let Err(err) = kill_current_game().await {
+            log::error!("Failed to stop session {}: {err}", session.id);
+        }
+    });
+    Ok(())
 }
 
 // currently saves to the devcade machine (or local machine if running locally) in the future,
@@ -741,6 +1176,13 @@ fn from_group(group: &str) -> (String, String) {
         "./.save"
     });
 
+    // Development games get their own save data so they can't clobber production saves
+    let save_path = if production() {
+        save_path.to_path_buf()
+    } else {
+        save_path.join("development")
+    };
+
     let mut parts: Vec<String> = group.split('/').map(|a| a.to_string()).collect();
     let group = parts.pop().unwrap_or_default();
     let save_path = save_path.join(parts.join("/"));
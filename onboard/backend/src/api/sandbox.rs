@@ -0,0 +1,242 @@
+use super::launcher::FlatpakLauncher;
+use crate::env::terminate_grace_period;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::TerminationStage;
+use std::collections::{HashMap, HashSet};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+/**
+ * How often to check whether a game's sandbox is still there while waiting for it to go away
+ */
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/**
+ * How long to wait for the sandbox to go away after SIGKILL or `flatpak kill`
+ */
+pub const KILL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/**
+ * How many times to look for processes that were started while a sandbox was being frozen
+ */
+const FREEZE_ROUNDS: usize = 3;
+
+/**
+ * Get the host PIDs of the processes flatpak started inside each running instance of an app.
+ *
+ * # Errors
+ * This function will return an error if `flatpak ps` cannot be run.
+ */
+pub async fn instance_pids(app_id: &str) -> Result<Vec<i32>, Error> {
+    ps(app_id, "child-pid").await
+}
+
+/**
+ * Get the host PIDs of the sandbox (bubblewrap) process of each running instance of an app.
+ *
+ * # Errors
+ * This function will return an error if `flatpak ps` cannot be run.
+ */
+pub async fn root_pids(app_id: &str) -> Result<Vec<i32>, Error> {
+    ps(app_id, "pid").await
+}
+
+/**
+ * Get the host PIDs of every process in each running instance of an app: the sandbox itself and
+ * everything running inside it.
+ *
+ * # Errors
+ * This function will return an error if `flatpak ps` cannot be run.
+ */
+pub async fn sandbox_pids(app_id: &str) -> Result<Vec<i32>, Error> {
+    Ok(descendants(&root_pids(app_id).await?))
+}
+
+/**
+ * Get the host PIDs of every process running inside each instance of an app: what flatpak
+ * started and everything it started in turn, but not the sandbox itself.
+ *
+ * # Errors
+ * This function will return an error if `flatpak ps` cannot be run.
+ */
+pub async fn app_pids(app_id: &str) -> Result<Vec<i32>, Error> {
+    Ok(descendants(&instance_pids(app_id).await?))
+}
+
+/**
+ * Get one PID column of `flatpak ps` for every running instance of an app
+ */
+async fn ps(app_id: &str, column: &str) -> Result<Vec<i32>, Error> {
+    // Instances are listed no matter which installation they were started from
+    let output = Command::new("flatpak")
+        .arg("ps")
+        .arg(format!("--columns={column},application"))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!("flatpak ps failed ({})", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let pid = columns.next()?.parse::<i32>().ok()?;
+            (columns.next()? == app_id).then_some(pid)
+        })
+        .collect())
+}
+
+/**
+ * Get the given processes and all of their descendants, by walking `/proc`
+ */
+fn descendants(roots: &[i32]) -> Vec<i32> {
+    let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+    if let Ok(entries) = std::fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+                continue;
+            };
+            // The parent PID is the second field after the command name, which can contain
+            // spaces and parentheses itself
+            let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+                continue;
+            };
+            let ppid = stat
+                .rsplit_once(')')
+                .and_then(|(_, rest)| rest.split_whitespace().nth(1))
+                .and_then(|ppid| ppid.parse::<i32>().ok());
+            if let Some(ppid) = ppid {
+                children.entry(ppid).or_default().push(pid);
+            }
+        }
+    }
+
+    let mut pids = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = roots.to_vec();
+    while let Some(pid) = queue.pop() {
+        if seen.insert(pid) {
+            pids.push(pid);
+            queue.extend(children.get(&pid).into_iter().flatten());
+        }
+    }
+    pids
+}
+
+/**
+ * Stop every process in an app's sandbox with SIGSTOP. Processes started while the sandbox is
+ * being frozen are looked for a few times so nothing is left running.
+ *
+ * # Errors
+ * This function will return an error if the app isn't running or `flatpak ps` cannot be run.
+ */
+pub async fn freeze(app_id: &str) -> Result<(), Error> {
+    let mut frozen = HashSet::new();
+    for _ in 0..FREEZE_ROUNDS {
+        let pids: Vec<i32> = sandbox_pids(app_id)
+            .await?
+            .into_iter()
+            .filter(|pid| !frozen.contains(pid))
+            .collect();
+        if pids.is_empty() {
+            break;
+        }
+        signal(&pids, libc::SIGSTOP);
+        frozen.extend(pids);
+    }
+    if frozen.is_empty() {
+        return Err(anyhow!("{app_id} isn't running"));
+    }
+    log::debug!("Froze {app_id} ({frozen:?})");
+    Ok(())
+}
+
+/**
+ * Continue every process in an app's sandbox with SIGCONT
+ *
+ * # Errors
+ * This function will return an error if `flatpak ps` cannot be run.
+ */
+pub async fn thaw(app_id: &str) -> Result<(), Error> {
+    let pids = sandbox_pids(app_id).await?;
+    signal(&pids, libc::SIGCONT);
+    log::debug!("Thawed {app_id} ({pids:?})");
+    Ok(())
+}
+
+/**
+ * Send a signal to each of the given processes. Processes that have already exited are ignored.
+ */
+pub fn signal(pids: &[i32], signal: libc::c_int) {
+    for pid in pids {
+        // SAFETY: kill has no memory safety requirements, it only looks at its arguments
+        if unsafe { libc::kill(*pid, signal) } != 0 {
+            log::debug!(
+                "Failed to send signal {signal} to {pid}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+/**
+ * Wait up to `timeout` for every instance of an app to go away. Returns whether they did.
+ */
+async fn wait_for_exit(app_id: &str, timeout: Duration) -> Result<bool, Error> {
+    let start = Instant::now();
+    loop {
+        if instance_pids(app_id).await?.is_empty() {
+            return Ok(true);
+        }
+        if start.elapsed() >= timeout {
+            return Ok(false);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/**
+ * Stop every running instance of an app, escalating until it is gone:
+ * SIGTERM to every process in the sandbox, so the game can flush its saves through the game
+ * socket and exit by itself, then SIGKILL to the whole sandbox after `terminate_grace_period`,
+ * then `flatpak kill` if the sandbox is still there.
+ * Returns the stage that was needed.
+ *
+ * # Errors
+ * This function will return an error if the sandbox could not be inspected, or if it is still
+ * there after every stage.
+ */
+pub async fn terminate(
+    launcher: &FlatpakLauncher,
+    app_id: &str,
+) -> Result<TerminationStage, Error> {
+    // Every process in the sandbox gets the chance to save, not just the one flatpak started.
+    // The sandbox itself is left out, since it takes everything inside down with it when it dies.
+    let pids = app_pids(app_id).await?;
+    if pids.is_empty() {
+        return Ok(TerminationStage::AlreadyExited);
+    }
+
+    log::debug!("Sending SIGTERM to {app_id} ({pids:?})");
+    signal(&pids, libc::SIGTERM);
+    if wait_for_exit(app_id, terminate_grace_period()).await? {
+        return Ok(TerminationStage::Terminated);
+    }
+
+    log::warn!("{app_id} didn't exit within its grace period, sending SIGKILL");
+    signal(&sandbox_pids(app_id).await?, libc::SIGKILL);
+    if wait_for_exit(app_id, KILL_TIMEOUT).await? {
+        return Ok(TerminationStage::Killed);
+    }
+
+    log::warn!("{app_id} is still running after SIGKILL, using flatpak kill");
+    launcher.kill_app(app_id).await?;
+    if wait_for_exit(app_id, KILL_TIMEOUT).await? {
+        return Ok(TerminationStage::FlatpakKilled);
+    }
+
+    Err(anyhow!("{app_id} is still running after flatpak kill"))
+}
@@ -0,0 +1,125 @@
+use crate::api::{game_list_from_fs, kill_current_game, launch_demo};
+use crate::config;
+use crate::env::{attract_demo_length, attract_idle};
+use crate::session;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/**
+ * How often attract mode checks whether to start or end a demo
+ */
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /**
+     * When a player last pressed something, or last had a game running
+     */
+    static ref LAST_ACTIVITY: Mutex<SystemTime> = Mutex::new(SystemTime::now());
+
+    /**
+     * When to try launching a demo again after one failed to launch
+     */
+    static ref RETRY_AT: Mutex<Option<SystemTime>> = Mutex::new(None);
+}
+
+/**
+ * Position in the rotation of the next demo to show
+ */
+static NEXT_DEMO: AtomicUsize = AtomicUsize::new(0);
+
+fn touch() {
+    *LAST_ACTIVITY.lock().unwrap() = SystemTime::now();
+}
+
+/**
+ * Record that a player pressed something, which also keeps the current session from going idle.
+ * If a demo is being shown, it is ended immediately so the frontend goes back to the menu.
+ */
+pub async fn player_input() {
+    touch();
+    session::heartbeat();
+    if session::current().is_some_and(|session| session.demo) {
+        log::info!("Player input, ending attract mode");
+        if let Err(err) = kill_current_game().await {
+            log::error!("Failed to end demo: {err}");
+        }
+    }
+}
+
+/**
+ * Pick the next game to show a demo of, rotating through the installed games that have a demo
+ * mode
+ */
+fn next_demo() -> Option<String> {
+    let config = config::load();
+    let mut games = game_list_from_fs()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|game| !game.broken)
+        .filter(|game| config.games.get(&game.id).is_some_and(|game| game.demo))
+        .map(|game| game.id)
+        .collect::<Vec<String>>();
+    if games.is_empty() {
+        return None;
+    }
+    games.sort();
+    let next = NEXT_DEMO.fetch_add(1, Ordering::SeqCst);
+    Some(games.swap_remove(next % games.len()))
+}
+
+/**
+ * Launch the next demo, waiting for a while before trying again if it can't be launched
+ */
+async fn start_demo() {
+    if RETRY_AT
+        .lock()
+        .unwrap()
+        .is_some_and(|retry_at| SystemTime::now() < retry_at)
+    {
+        return;
+    }
+    let Some(game_id) = next_demo() else {
+        return;
+    };
+    log::info!("Showing a demo of {game_id}");
+    if let Err(err) = launch_demo(game_id.clone()).await {
+        log::error!("Failed to show a demo of {game_id}: {err}");
+        *RETRY_AT.lock().unwrap() = Some(SystemTime::now() + attract_demo_length());
+    }
+}
+
+/**
+ * Run attract mode: once nobody has pressed anything or played a game for `attract_idle`, show a
+ * demo of each game that has a demo mode for `attract_demo_length`, one after the other, until a
+ * player presses something.
+ */
+pub async fn run() {
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        let Some(idle) = attract_idle() else {
+            continue;
+        };
+        match session::current() {
+            Some(session) if session.demo => {
+                // Only count the time the demo has been running, not how long it took to launch
+                let shown = session.running_since.is_some() && !session.stopping;
+                if shown && session.play_time() >= attract_demo_length() {
+                    log::debug!("Demo of {} is over", session.game_id);
+                    if let Err(err) = kill_current_game().await {
+                        log::error!("Failed to end demo: {err}");
+                    }
+                }
+            }
+            // Somebody is playing, so the cabinet isn't idle
+            Some(_) => touch(),
+            None => {
+                let idle_for = LAST_ACTIVITY.lock().unwrap().elapsed().unwrap_or_default();
+                if idle_for >= idle {
+                    start_demo().await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Check a game's flatpak bundle against the cabinet's install policy before uploading it.
+//!
+//! Usage: `devcade-bundle-check <bundle.flatpak> [--game <game id>] [--config <config.json>]`
+//!
+//! Exits with 0 if the cabinet would install the bundle, 1 if it would reject it, and 2 if the
+//! bundle couldn't be read.
+
+use backend::policy;
+use libflatpak::gio::glib::{KeyFile, KeyFileFlags};
+use libflatpak::{gio, prelude::*, BundleRef};
+use std::process::exit;
+
+struct Args {
+    bundle: String,
+    game_id: Option<String>,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: devcade-bundle-check <bundle.flatpak> [--game <game id>] [--config <config.json>]"
+    );
+    exit(2);
+}
+
+fn parse_args() -> Args {
+    let mut bundle = None;
+    let mut game_id = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--game" => game_id = Some(args.next().unwrap_or_else(|| usage())),
+            // The policy is read from the file the backend would use
+            "--config" => {
+                std::env::set_var("DEVCADE_CONFIG", args.next().unwrap_or_else(|| usage()))
+            }
+            "-h" | "--help" => usage(),
+            _ if bundle.is_none() => bundle = Some(arg),
+            _ => usage(),
+        }
+    }
+    Args {
+        bundle: bundle.unwrap_or_else(|| usage()),
+        game_id,
+    }
+}
+
+fn main() {
+    let args = parse_args();
+
+    let bundle = match BundleRef::new(&gio::File::for_path(&args.bundle)) {
+        Ok(bundle) => bundle,
+        Err(err) => {
+            eprintln!("Couldn't open bundle {}: {err}", args.bundle);
+            exit(2);
+        }
+    };
+    let metadata = KeyFile::new();
+    let loaded = bundle
+        .metadata()
+        .map(|bytes| metadata.load_from_bytes(&bytes, KeyFileFlags::NONE));
+    if !matches!(loaded, Some(Ok(()))) {
+        eprintln!("Bundle {} has no readable metadata", args.bundle);
+        exit(2);
+    }
+
+    let field = |value: Option<String>| value.unwrap_or_else(|| String::from("unknown"));
+    println!("Bundle:         {}", args.bundle);
+    println!(
+        "App ID:         {}",
+        field(bundle.name().map(|name| name.to_string()))
+    );
+    println!(
+        "Arch:           {}",
+        field(bundle.arch().map(|arch| arch.to_string()))
+    );
+    println!(
+        "Branch:         {}",
+        field(bundle.branch().map(|branch| branch.to_string()))
+    );
+    println!(
+        "Runtime:        {}",
+        field(
+            metadata
+                .string("Application", "runtime")
+                .ok()
+                .map(|runtime| runtime.to_string())
+        )
+    );
+    println!("Installed size: {} bytes", bundle.installed_size());
+    println!();
+    println!("Metadata:");
+    for line in metadata.to_data().lines() {
+        println!("    {line}");
+    }
+    println!();
+
+    let context = match policy::context(&metadata) {
+        Ok(context) => context,
+        Err(err) => {
+            eprintln!("Couldn't read the permissions of the bundle: {err}");
+            exit(2);
+        }
+    };
+    let policy = match policy::current() {
+        Ok(policy) => policy,
+        Err(err) => {
+            eprintln!("Couldn't load the sandbox policy: {err}");
+            exit(2);
+        }
+    };
+    let rules = match &args.game_id {
+        Some(game_id) => policy::for_game(&policy, game_id),
+        None => policy::for_game(&policy, ""),
+    };
+    let checks = policy::evaluate(&rules, context);
+
+    println!("Permissions:");
+    if checks.is_empty() {
+        println!("    (none requested)");
+    }
+    for check in &checks {
+        println!(
+            "    [{}] {}={}: {}",
+            if check.allowed { " OK " } else { "DENY" },
+            check.realm,
+            check.permission,
+            check.explanation
+        );
+    }
+    println!();
+
+    let denied = checks.iter().filter(|check| !check.allowed).count();
+    if denied > 0 {
+        println!("REJECTED: the cabinet would refuse to install this bundle ({denied} permission(s) not allowed)");
+        exit(1);
+    }
+    println!("OK: the cabinet would install this bundle");
+}
@@ -1,12 +1,44 @@
-use crate::api::{self, nfc_user};
+use crate::api::nfc_user;
 
 use crate::api::{
-    download_banner, download_game, download_icon, game_list, game_list_from_fs, kill_current_game,
-    launch_game, nfc_tags, persistence_flush, persistence_load, persistence_save, tag_games,
-    tag_list, user,
+    collect_garbage, download_banner, download_game, download_icon, exit_to_menu, game_list,
+    game_list_from_fs, kill_current_game, launch_game, nfc_tags, pause_game, persistence_flush,
+    persistence_load, persistence_save, prefetch_assets, resume_game, rollback_game, session_info,
+    tag_games, tag_list, user, verify_installations,
 };
 use devcade_onboard_types::{RequestBody, ResponseBody};
 
+/**
+ * Handle a request from a game on the game socket, after `session::authorize` found it was sent
+ * by `game_id`. Save data is kept per game, so it is stored under that game's ID.
+ */
+pub async fn handle_game(req: RequestBody, game_id: &str) -> ResponseBody {
+    match req {
+        RequestBody::Save(group, key, value) => {
+            let group = format!("{game_id}/{group}");
+            match persistence_save(group.as_str(), key.as_str(), value.as_str()).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::Load(group, key) => {
+            let group = format!("{game_id}/{group}");
+            match persistence_load(group.as_str(), key.as_str()).await {
+                Ok(s) => ResponseBody::Object(s),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::Flush => {
+            log::debug!("Flushing save data for {game_id}");
+            match persistence_flush().await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        req => handle(req).await,
+    }
+}
+
 /**
  * Handle a request from the frontend.
  */
@@ -26,7 +58,7 @@ pub async fn handle(req: RequestBody) -> ResponseBody {
         },
         RequestBody::GetGame(game_id) => match game_list().await {
             Ok(game) => match game.into_iter().find(|g| g.id == game_id) {
-                Some(game) => ResponseBody::Game(game),
+                Some(game) => ResponseBody::Game(Box::new(game)),
                 None => ResponseBody::Err(format!("Game with ID {game_id} not found")),
             },
             Err(err) => err.into(),
@@ -35,6 +67,18 @@ pub async fn handle(req: RequestBody) -> ResponseBody {
             Ok(_) => ResponseBody::Ok,
             Err(err) => err.into(),
         },
+        RequestBody::RollbackGame(game_id) => match rollback_game(game_id).await {
+            Ok(game) => ResponseBody::Game(Box::new(game)),
+            Err(err) => err.into(),
+        },
+        RequestBody::VerifyInstallations(repair) => match verify_installations(repair).await {
+            Ok(report) => ResponseBody::InstallReport(report),
+            Err(err) => err.into(),
+        },
+        RequestBody::CollectGarbage => match collect_garbage().await {
+            Ok(report) => ResponseBody::GcReport(report),
+            Err(err) => err.into(),
+        },
         RequestBody::DownloadIcon(game_id) => match download_icon(game_id).await {
             Ok(_) => ResponseBody::Ok,
             Err(err) => err.into(),
@@ -43,15 +87,67 @@ pub async fn handle(req: RequestBody) -> ResponseBody {
             Ok(_) => ResponseBody::Ok,
             Err(err) => err.into(),
         },
+        RequestBody::PrefetchAssets(game_ids) => match prefetch_assets(game_ids).await {
+            Ok(results) => ResponseBody::AssetPrefetch(results),
+            Err(err) => err.into(),
+        },
         RequestBody::LaunchGame(game_id) => match launch_game(game_id).await {
-            Ok(_) => ResponseBody::Ok,
+            Ok(session_id) => ResponseBody::SessionId(session_id),
             Err(err) => err.into(),
         },
+        RequestBody::GetCurrentSession => {
+            ResponseBody::Session(crate::session::current().map(|session| session.info()))
+        }
+        RequestBody::GetGameLogs(game_id, session_id) => {
+            match crate::session::logs::read(game_id.as_str(), session_id).await {
+                Ok(log) => ResponseBody::GameLog(log),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::GetGameStats(game_id) => {
+            ResponseBody::GameStats(crate::session::stats::get(game_id.as_str()).await)
+        }
+        RequestBody::GetAllStats => ResponseBody::AllStats(crate::session::stats::all().await),
+        RequestBody::GetPolicy(game_id) => match crate::policy::current() {
+            Ok(policy) => ResponseBody::Policy(match game_id {
+                Some(game_id) => crate::policy::for_game(&policy, game_id.as_str()),
+                None => policy,
+            }),
+            Err(err) => {
+                log::error!("Couldn't load the sandbox policy: {err}");
+                err.into()
+            }
+        },
+        RequestBody::PlayerInput => {
+            crate::attract::player_input().await;
+            ResponseBody::Ok
+        }
+        RequestBody::Heartbeat => {
+            crate::session::heartbeat();
+            ResponseBody::Ok
+        }
+        // Subscriptions are set up by the onboard server, which owns the connection
+        RequestBody::SubscribeEvents => ResponseBody::Ok,
         RequestBody::KillGame => match kill_current_game().await {
-            Ok(_) => ResponseBody::Ok,
+            Ok(stage) => ResponseBody::Terminated(stage),
+            Err(err) => err.into(),
+        },
+        RequestBody::PauseGame => match pause_game().await {
+            Ok(()) => ResponseBody::Ok,
+            Err(err) => err.into(),
+        },
+        RequestBody::ResumeGame => match resume_game().await {
+            Ok(()) => ResponseBody::Ok,
             Err(err) => err.into(),
         },
         RequestBody::SetProduction(prod) => {
+            // The running game's install and saves belong to the current environment
+            if let Some(session) = crate::session::current() {
+                return ResponseBody::Err(format!(
+                    "Can't switch API environment while game {} is running",
+                    session.game_id
+                ));
+            }
             crate::env::set_production(prod);
             ResponseBody::Ok
         }
@@ -82,23 +178,26 @@ pub async fn handle(req: RequestBody) -> ResponseBody {
             Ok(user) => ResponseBody::NfcUser(user),
             Err(err) => err.into(),
         },
-        RequestBody::Save(group, key, value) => {
-            let group = format!("{}/{}", api::current_game().unwrap().id, group);
-            match persistence_save(group.as_str(), key.as_str(), value.as_str()).await {
-                Ok(()) => ResponseBody::Ok,
-                Err(err) => err.into(),
-            }
-        }
-        RequestBody::Load(group, key) => {
-            let group = format!("{}/{}", api::current_game().unwrap().id, group);
-            match persistence_load(group.as_str(), key.as_str()).await {
-                Ok(s) => ResponseBody::Object(s),
-                Err(err) => err.into(),
-            }
+        RequestBody::Save(_, _, _) | RequestBody::Load(_, _) => {
+            ResponseBody::Err(String::from("Save data can only be used by a game"))
         }
         RequestBody::Flush => match persistence_flush().await {
             Ok(()) => ResponseBody::Ok,
             Err(err) => err.into(),
         },
+        RequestBody::ExitToMenu => match exit_to_menu() {
+            Ok(()) => ResponseBody::Ok,
+            Err(err) => err.into(),
+        },
+        RequestBody::GetSessionInfo => match session_info().await {
+            Ok(info) => ResponseBody::GameSessionInfo(info),
+            Err(err) => err.into(),
+        },
+        RequestBody::Notify(text, severity) => {
+            match crate::session::notify::notify(text.as_str(), severity) {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
     }
 }
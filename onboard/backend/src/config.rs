@@ -0,0 +1,178 @@
+use crate::env::config_path;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::{DevcadeGame, LaunchOptions};
+use devcade_onboard_types::SandboxPolicy;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/**
+ * Settings read from the JSON file at `config_path`. Every field is optional, and the file itself
+ * doesn't need to exist.
+ */
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /**
+     * Resource limits applied to every game
+     */
+    pub limits: ResourceLimits,
+
+    /**
+     * Settings for specific games, by game ID
+     */
+    pub games: HashMap<String, GameConfig>,
+
+    /**
+     * Which flatpak permissions games may ask for and are given when launched. Fields that aren't
+     * set keep the values of `policy::cabinet_default`.
+     */
+    #[serde(deserialize_with = "crate::policy::deserialize")]
+    pub policy: SandboxPolicy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            limits: ResourceLimits::default(),
+            games: HashMap::new(),
+            policy: crate::policy::cabinet_default(),
+        }
+    }
+}
+
+/**
+ * Settings for a specific game, overriding the global ones
+ */
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct GameConfig {
+    /**
+     * Resource limits for this game. Limits that aren't set fall back to the global ones.
+     */
+    pub limits: ResourceLimits,
+
+    /**
+     * Executable the native launcher runs, relative to the game's directory
+     */
+    pub executable: Option<String>,
+
+    /**
+     * Whether the game has a demo mode that attract mode can show. Games are launched with
+     * `DEVCADE_DEMO_MODE=1` when they are shown as a demo.
+     */
+    pub demo: bool,
+
+    /**
+     * Arguments and environment to launch the game with instead of the ones from the catalog
+     */
+    pub launch: Option<LaunchOptions>,
+}
+
+/**
+ * Limits on the resources a game's sandbox can use. A limit that is `None` isn't enforced.
+ */
+#[derive(Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /**
+     * Maximum memory used by everything in the sandbox, in MiB
+     */
+    pub memory_mb: Option<u64>,
+
+    /**
+     * Maximum CPU time the sandbox gets, in percent of one CPU (200 is two whole CPUs)
+     */
+    pub cpu_percent: Option<u32>,
+
+    /**
+     * Maximum number of processes and threads in the sandbox
+     */
+    pub max_processes: Option<u64>,
+
+    /**
+     * Maximum number of files each process in the sandbox can have open
+     */
+    pub max_open_files: Option<u64>,
+}
+
+impl ResourceLimits {
+    /**
+     * Use the limits set in `overrides`, falling back to these for the ones that aren't set
+     */
+    #[must_use]
+    pub fn overridden_by(&self, overrides: &ResourceLimits) -> ResourceLimits {
+        ResourceLimits {
+            memory_mb: overrides.memory_mb.or(self.memory_mb),
+            cpu_percent: overrides.cpu_percent.or(self.cpu_percent),
+            max_processes: overrides.max_processes.or(self.max_processes),
+            max_open_files: overrides.max_open_files.or(self.max_open_files),
+        }
+    }
+
+    /**
+     * Whether no limits are set
+     */
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == ResourceLimits::default()
+    }
+}
+
+impl Config {
+    /**
+     * Get the resource limits for a game, with its own limits taking precedence over the global
+     * ones
+     */
+    #[must_use]
+    pub fn limits_for(&self, game_id: &str) -> ResourceLimits {
+        match self.games.get(game_id) {
+            Some(game) => self.limits.overridden_by(&game.limits),
+            None => self.limits.clone(),
+        }
+    }
+
+    /**
+     * Get the arguments and environment a game asks to be launched with, from the config if it
+     * overrides them and from the catalog otherwise. These still have to be checked against the
+     * sandbox policy with `policy::launch_options`.
+     */
+    #[must_use]
+    pub fn launch_for(&self, game: &DevcadeGame) -> LaunchOptions {
+        self.games
+            .get(game.id.as_str())
+            .and_then(|config| config.launch.clone())
+            .unwrap_or_else(|| game.launch.clone())
+    }
+}
+
+/**
+ * Read the config file. This is done every time a setting is needed so changes apply to the next
+ * game without restarting the backend. If the file is missing or invalid, the defaults are used.
+ */
+#[must_use]
+pub fn load() -> Config {
+    match try_load() {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("{err}, using the defaults");
+            Config::default()
+        }
+    }
+}
+
+/**
+ * Read the config file, using the defaults only if it doesn't exist.
+ *
+ * # Errors
+ * This function will return an error if the file exists but can't be read or isn't valid.
+ */
+pub fn try_load() -> Result<Config, Error> {
+    let path = config_path();
+    let json = match std::fs::read_to_string(path.as_str()) {
+        Ok(json) => json,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(err) => return Err(anyhow!("Failed to read config file {path}: {err}")),
+    };
+    serde_json::from_str(json.as_str())
+        .map_err(|err| anyhow!("Config file {path} is invalid: {err}"))
+}
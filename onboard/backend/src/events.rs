@@ -0,0 +1,30 @@
+use devcade_onboard_types::Event;
+use lazy_static::lazy_static;
+use tokio::sync::broadcast;
+
+/**
+ * How many events a slow subscriber can fall behind before it starts missing events
+ */
+const EVENT_BUFFER: usize = 64;
+
+lazy_static! {
+    static ref EVENTS: broadcast::Sender<Event> = broadcast::channel(EVENT_BUFFER).0;
+}
+
+/**
+ * Send an event to everyone that is subscribed to backend events. Events sent while nobody is
+ * subscribed are dropped.
+ */
+pub fn emit(event: Event) {
+    log::debug!("Event: {event}");
+    // An error only means nobody is listening right now
+    let _ = EVENTS.send(event);
+}
+
+/**
+ * Subscribe to all events emitted from now on
+ */
+#[must_use]
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    EVENTS.subscribe()
+}
@@ -18,6 +18,32 @@ pub mod command;
  */
 pub mod nfc;
 
+/**
+ * Module for keeping track of the game that is currently being played
+ */
+pub mod session;
+
+/**
+ * Module for notifying the frontend of things it didn't ask about, like a game exiting
+ */
+pub mod events;
+
+/**
+ * Module for reading the backend's config file, for settings that are too structured for
+ * environment variables
+ */
+pub mod config;
+
+/**
+ * Module for deciding which flatpak permissions games may ask for and are given
+ */
+pub mod policy;
+
+/**
+ * Module for showing demos of games while the cabinet isn't being used
+ */
+pub mod attract;
+
 /**
  * Module for safely getting environment variables, logging any errors that occur and providing
  * default values.
@@ -26,7 +52,10 @@ pub mod env {
     // TODO Cache env vars? Probably not necessary
     use log::{log, Level};
     use std::env;
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
     use std::sync::Mutex;
+    use std::time::Duration;
 
     // TODO should be Mutex? Lmao
     static PRODUCTION: Mutex<bool> = Mutex::new(true);
@@ -54,6 +83,71 @@ pub mod env {
         }
     }
 
+    /**
+     * Get the name of an API environment, which separates everything the backend keeps for it
+     * from the other environment's
+     */
+    fn environment(production: bool) -> &'static str {
+        if production {
+            "production"
+        } else {
+            "development"
+        }
+    }
+
+    /**
+     * Get the directory that games for the current API environment are installed into, see
+     * [`games_path_for`]
+     */
+    #[must_use]
+    pub fn games_path() -> String {
+        games_path_for(production())
+    }
+
+    /**
+     * Get the directory that games for an API environment are installed into. Each API
+     * environment has its own subdirectory of the devcade directory, so that switching APIs never
+     * overwrites the other environment's games. The directory holds nothing but game
+     * directories, named by game ID.
+     */
+    #[must_use]
+    pub fn games_path_for(production: bool) -> String {
+        format!("{}/{}", devcade_path(), environment(production))
+    }
+
+    /**
+     * Get the directory the backend keeps its own files for the current API environment in, see
+     * [`state_path_for`]
+     */
+    #[must_use]
+    pub fn state_path() -> String {
+        state_path_for(production())
+    }
+
+    /**
+     * Get the directory the backend keeps its own files for an API environment in, like play
+     * statistics. It is kept apart from the games directory so nothing in it can be mistaken for
+     * a game.
+     */
+    #[must_use]
+    pub fn state_path_for(production: bool) -> String {
+        format!("{}/state/{}", devcade_path(), environment(production))
+    }
+
+    /**
+     * Get the path of the flatpak user installation used for the current API environment.
+     * Production games are installed into the regular user installation, so this returns `None`
+     * in production. Development games get their own installation inside the devcade directory.
+     */
+    #[must_use]
+    pub fn flatpak_user_dir() -> Option<String> {
+        if production() {
+            None
+        } else {
+            Some(format!("{}/flatpak-development", devcade_path()))
+        }
+    }
+
     /**
      * Get the URL of the API. This is where games are downloaded from.
      * If the value is not set in the environment, it will throw a fatal error and panic.
@@ -79,6 +173,257 @@ pub mod env {
         }
     }
 
+    /**
+     * Whether the backend is currently talking to the production API.
+     */
+    #[must_use]
+    pub fn production() -> bool {
+        *PRODUCTION.lock().unwrap()
+    }
+
+    /**
+     * Get the path of the backend's JSON config file. If the value is not set in the environment,
+     * it will default to `config.json` in the devcade path.
+     */
+    #[must_use]
+    pub fn config_path() -> String {
+        env::var("DEVCADE_CONFIG").unwrap_or_else(|_| format!("{}/config.json", devcade_path()))
+    }
+
+    /**
+     * Get the name of the launcher games are installed and run with in the current API
+     * environment: `flatpak` (the default) or `native`. Development uses `DEVCADE_DEV_LAUNCHER`
+     * if it is set, and `DEVCADE_LAUNCHER` otherwise.
+     */
+    #[must_use]
+    pub fn launcher() -> String {
+        let launcher = if production() {
+            env::var("DEVCADE_LAUNCHER")
+        } else {
+            env::var("DEVCADE_DEV_LAUNCHER").or_else(|_| env::var("DEVCADE_LAUNCHER"))
+        };
+        launcher.unwrap_or_else(|_| String::from("flatpak"))
+    }
+
+    /**
+     * Get a positive number from the environment, falling back to `default` if it is not set or
+     * is not a positive number.
+     */
+    fn positive_var(name: &str, default: u64) -> u64 {
+        match env::var(name).map(|value| value.parse::<u64>()) {
+            Ok(Ok(value)) if value > 0 => value,
+            Ok(_) => {
+                log!(
+                    Level::Warn,
+                    "{name} must be a positive number, falling back to {default}"
+                );
+                default
+            }
+            Err(_) => default,
+        }
+    }
+
+    /**
+     * Get a number of seconds from the environment. Returns `None` if it is not set, set to 0, or
+     * is not a number.
+     */
+    fn optional_secs(name: &str) -> Option<Duration> {
+        match env::var(name).map(|value| value.parse::<u64>()) {
+            Ok(Ok(0)) | Err(_) => None,
+            Ok(Ok(secs)) => Some(Duration::from_secs(secs)),
+            Ok(Err(_)) => {
+                log!(
+                    Level::Warn,
+                    "{name} must be a number of seconds, ignoring it"
+                );
+                None
+            }
+        }
+    }
+
+    /**
+     * Get the maximum length of a game session, after which the watchdog ends it. Sessions have
+     * no maximum length unless `DEVCADE_MAX_SESSION_SECS` is set.
+     */
+    #[must_use]
+    pub fn max_session_length() -> Option<Duration> {
+        optional_secs("DEVCADE_MAX_SESSION_SECS")
+    }
+
+    /**
+     * Get how long a game session can go without a heartbeat before the watchdog ends it.
+     * Sessions never time out unless `DEVCADE_IDLE_TIMEOUT_SECS` is set.
+     */
+    #[must_use]
+    pub fn idle_timeout() -> Option<Duration> {
+        optional_secs("DEVCADE_IDLE_TIMEOUT_SECS")
+    }
+
+    /**
+     * Get the name of the cabinet, which games are told so they can tell cabinets apart. If the
+     * value is not set in the environment, the machine's hostname is used.
+     */
+    #[must_use]
+    pub fn machine_name() -> String {
+        env::var("DEVCADE_MACHINE_NAME")
+            .or_else(|_| env::var("HOSTNAME"))
+            .ok()
+            .or_else(|| {
+                std::fs::read_to_string("/etc/hostname")
+                    .ok()
+                    .map(|name| name.trim().to_owned())
+            })
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| String::from("devcade"))
+    }
+
+    /**
+     * Get the UID of a user given by name or UID
+     */
+    fn uid_of(user: &str) -> Option<u32> {
+        if let Ok(uid) = user.parse() {
+            return Some(uid);
+        }
+        let name = CString::new(user).ok()?;
+        let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+        let mut buffer = vec![0 as libc::c_char; 4096];
+        let mut result = std::ptr::null_mut();
+        // SAFETY: every pointer is valid until the call returns, and the buffer's length is
+        // passed along with it
+        let status = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                passwd.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        if status != 0 || result.is_null() {
+            return None;
+        }
+        // SAFETY: getpwnam_r filled in the entry, since it found the user
+        Some(unsafe { passwd.assume_init() }.pw_uid)
+    }
+
+    /**
+     * Get the UIDs of the users whose processes may connect to the onboard socket, from the
+     * comma separated names or UIDs in `DEVCADE_ONBOARD_USERS`. If the value is not set in the
+     * environment, only the user the backend runs as may connect.
+     */
+    #[must_use]
+    pub fn onboard_users() -> Vec<u32> {
+        let Ok(users) = env::var("DEVCADE_ONBOARD_USERS") else {
+            // SAFETY: geteuid has no memory safety requirements and can't fail
+            return vec![unsafe { libc::geteuid() }];
+        };
+        users
+            .split(',')
+            .map(str::trim)
+            .filter(|user| !user.is_empty())
+            .filter_map(|user| {
+                let uid = uid_of(user);
+                if uid.is_none() {
+                    log!(
+                        Level::Warn,
+                        "Unknown user '{user}' in DEVCADE_ONBOARD_USERS, ignoring it"
+                    );
+                }
+                uid
+            })
+            .collect()
+    }
+
+    /**
+     * Get how many players the cabinet has controls for. If the value is not set in the
+     * environment, it will default to 2.
+     */
+    #[must_use]
+    pub fn player_count() -> u64 {
+        positive_var("DEVCADE_PLAYER_COUNT", 2)
+    }
+
+    /**
+     * Get how long the cabinet has to go without input before attract mode starts showing game
+     * demos. Attract mode is disabled unless `DEVCADE_ATTRACT_IDLE_SECS` is set.
+     */
+    #[must_use]
+    pub fn attract_idle() -> Option<Duration> {
+        optional_secs("DEVCADE_ATTRACT_IDLE_SECS")
+    }
+
+    /**
+     * Get how long each game demo in attract mode is shown for. If the value is not set in the
+     * environment, it will default to 60 seconds.
+     */
+    #[must_use]
+    pub fn attract_demo_length() -> Duration {
+        Duration::from_secs(positive_var("DEVCADE_ATTRACT_DEMO_SECS", 60))
+    }
+
+    /**
+     * Get how long before ending a session the watchdog warns the player. If the value is not set
+     * in the environment, it will default to 60 seconds.
+     */
+    #[must_use]
+    pub fn watchdog_warning() -> Duration {
+        Duration::from_secs(positive_var("DEVCADE_WATCHDOG_WARNING_SECS", 60))
+    }
+
+    /**
+     * Get how long a game gets to exit after being asked to before it is killed. If the value is
+     * not set in the environment, it will default to 5 seconds.
+     */
+    #[must_use]
+    pub fn terminate_grace_period() -> Duration {
+        Duration::from_secs(positive_var("DEVCADE_TERMINATE_GRACE_SECS", 5))
+    }
+
+    /**
+     * Get the maximum number of games whose artwork is downloaded at the same time when
+     * prefetching assets. If the value is not set in the environment, it will default to 8.
+     */
+    #[must_use]
+    pub fn asset_download_concurrency() -> usize {
+        positive_var("DEVCADE_ASSET_CONCURRENCY", 8) as usize
+    }
+
+    /**
+     * Get the maximum size in bytes of the output kept for a single game session. If the value is
+     * not set in the environment, it will default to 1 MiB.
+     */
+    #[must_use]
+    pub fn game_log_max_bytes() -> u64 {
+        positive_var("DEVCADE_GAME_LOG_MAX_BYTES", 1024 * 1024)
+    }
+
+    /**
+     * Get the number of session logs kept for each game. If the value is not set in the
+     * environment, it will default to 10.
+     */
+    #[must_use]
+    pub fn game_logs_kept() -> usize {
+        positive_var("DEVCADE_GAME_LOGS_KEPT", 10) as usize
+    }
+
+    /**
+     * Get how many times in a row a version of a game has to crash before it is marked broken. If
+     * the value is not set in the environment, it will default to 3.
+     */
+    #[must_use]
+    pub fn crash_threshold() -> u32 {
+        positive_var("DEVCADE_CRASH_THRESHOLD", 3) as u32
+    }
+
+    /**
+     * Whether games marked broken should be left out of game lists instead of just being flagged.
+     * This is enabled by setting `DEVCADE_HIDE_BROKEN_GAMES` to `true`.
+     */
+    #[must_use]
+    pub fn hide_broken_games() -> bool {
+        env::var("DEVCADE_HIDE_BROKEN_GAMES").is_ok_and(|value| value == "true")
+    }
+
     /**
      * Sets whether the API will interact with the production or development API.
      */
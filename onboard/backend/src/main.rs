@@ -1,10 +1,75 @@
+use backend::api::{collect_garbage, verify_installations};
 use backend::env::devcade_path;
 use backend::nfc::NFC_CLIENT;
 use backend::servers::path::{game_pipe, onboard_pipe};
 use backend::servers::ThreadHandles;
+use backend::session::stats;
 use log::{log, Level};
 use tokio::fs;
 
+/**
+ * Check the installed games and print what is wrong with them, repairing it if `repair` is set.
+ * Returns the exit code: 1 if anything is still wrong, 2 if the check couldn't be done.
+ */
+async fn verify(repair: bool) -> i32 {
+    let report = match verify_installations(repair).await {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Couldn't verify installed games: {err}");
+            return 2;
+        }
+    };
+    if !report.catalog_checked {
+        println!("The catalog couldn't be fetched, orphaned and outdated games aren't reported");
+    }
+    for issue in &report.issues {
+        let status = match (&issue.repair_error, issue.repaired) {
+            (Some(err), _) => format!(" (not repaired: {err})"),
+            (None, true) => String::from(" (repaired)"),
+            (None, false) => String::new(),
+        };
+        println!(
+            "{}: {:?}: {}{status}",
+            issue.game_id, issue.problem, issue.detail
+        );
+    }
+    println!(
+        "Checked {} games, found {} issues",
+        report.games_checked,
+        report.issues.len()
+    );
+    match report.issues.iter().all(|issue| issue.repaired) {
+        true => 0,
+        false => 1,
+    }
+}
+
+/**
+ * Delete what the installed games don't need anymore and print what was deleted. Returns the
+ * exit code.
+ */
+async fn gc() -> i32 {
+    match collect_garbage().await {
+        Ok(report) => {
+            for file in &report.files_removed {
+                println!("Removed {file}");
+            }
+            for app in &report.apps_uninstalled {
+                println!("Uninstalled app {app}");
+            }
+            for runtime in &report.runtimes_uninstalled {
+                println!("Uninstalled runtime {runtime}");
+            }
+            println!("Reclaimed {} bytes", report.bytes_reclaimed);
+            0
+        }
+        Err(err) => {
+            eprintln!("Couldn't collect garbage: {err}");
+            1
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> ! {
     #[cfg(not(target_os = "linux"))]
@@ -23,9 +88,36 @@ async fn main() -> ! {
     fs::create_dir_all(devcade_path())
         .await
         .expect("Couldn't create devcade dir");
+    if let Err(err) = backend::api::migrate_layout().await {
+        log!(
+            Level::Error,
+            "Couldn't move games to where they are kept now: {err}"
+        );
+    }
+
+    // `backend verify [--repair]`, `backend gc` and `backend stats [--csv]` do maintenance
+    // instead of running the backend
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("verify") => {
+            std::process::exit(verify(args.iter().any(|arg| arg == "--repair")).await)
+        }
+        Some("gc") => std::process::exit(gc().await),
+        Some("stats") => {
+            let all = stats::all().await;
+            match args.iter().any(|arg| arg == "--csv") {
+                true => print!("{}", stats::to_csv(&all)),
+                false => println!("{}", serde_json::to_string_pretty(&all).unwrap_or_default()),
+            }
+            std::process::exit(0)
+        }
+        _ => {}
+    }
 
     let mut handles: ThreadHandles = ThreadHandles::new();
 
+    tokio::spawn(backend::attract::run());
+
     handles.restart_onboard(onboard_pipe());
 
     handles.restart_game(game_pipe());
@@ -0,0 +1,268 @@
+use crate::config;
+use crate::env::devcade_path;
+use crate::servers::path::game_pipe;
+use anyhow::Error;
+use devcade_onboard_types::schema::LaunchOptions;
+use devcade_onboard_types::SandboxPolicy;
+use libflatpak::gio::glib::KeyFile;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/**
+ * Prefix of the environment variables the backend sets for every game, which games can't set
+ * themselves
+ */
+const BACKEND_ENV_PREFIX: &str = "DEVCADE_";
+
+/**
+ * Whether one permission a flatpak asks for is allowed, and why
+ */
+#[derive(Clone, Debug)]
+pub struct PermissionCheck {
+    pub realm: String,
+    pub permission: String,
+    pub allowed: bool,
+    pub explanation: String,
+}
+
+/**
+ * Get the sandbox policy from the config file, or the default one if it doesn't set one
+ *
+ * # Errors
+ * This function will return an error if the config file can't be read or parsed, since falling
+ * back to the defaults would quietly change what games are allowed to do.
+ */
+pub fn current() -> Result<SandboxPolicy, Error> {
+    Ok(config::try_load()?.policy)
+}
+
+/**
+ * The policy used when the config file doesn't set one. It allows what games on the cabinet need:
+ * the display, sound, controllers and the backend's sockets.
+ */
+#[must_use]
+pub fn cabinet_default() -> SandboxPolicy {
+    let set = |entries: &[&str]| {
+        entries
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect::<BTreeSet<String>>()
+    };
+    SandboxPolicy {
+        allowed: BTreeMap::from([
+            (String::from("shared"), set(&["network", "ipc"])),
+            (String::from("sockets"), set(&["x11", "pulseaudio"])),
+            (String::from("devices"), set(&["dri", "input"])),
+            (
+                String::from("filesystems"),
+                BTreeSet::from([format!("{}/persistence.sock", devcade_path()), game_pipe()]),
+            ),
+        ]),
+        deny_unknown_realms: true,
+        launch_args: [
+            "--device=dri",
+            "--socket=wayland",
+            "--socket=x11",
+            "--socket=fallback-x11",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect(),
+        allowed_env: set(&["SDL_*", "DOTNET_*", "MONO_*"]),
+        games: BTreeMap::new(),
+    }
+}
+
+/**
+ * Deserialize the policy from the config file on top of [`cabinet_default`], so a config that
+ * only sets some fields, like a game's exceptions, keeps the defaults for the rest.
+ *
+ * # Errors
+ * This function will return an error if the policy isn't an object, or a field is invalid.
+ */
+pub fn deserialize<'de, D>(deserializer: D) -> Result<SandboxPolicy, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let overrides = Map::<String, Value>::deserialize(deserializer)?;
+    let mut policy = serde_json::to_value(cabinet_default()).map_err(D::Error::custom)?;
+    if let Value::Object(fields) = &mut policy {
+        fields.extend(overrides);
+    }
+    serde_json::from_value(policy).map_err(D::Error::custom)
+}
+
+/**
+ * Get the rules that apply to a specific game: the global rules with the game's exceptions added.
+ * The result has no game exceptions of its own.
+ */
+#[must_use]
+pub fn for_game(policy: &SandboxPolicy, game_id: &str) -> SandboxPolicy {
+    let mut rules = SandboxPolicy {
+        games: Default::default(),
+        ..policy.clone()
+    };
+    if let Some(exceptions) = policy.games.get(game_id) {
+        for (realm, permissions) in &exceptions.allowed {
+            rules
+                .allowed
+                .entry(realm.clone())
+                .or_default()
+                .extend(permissions.iter().cloned());
+        }
+        rules
+            .launch_args
+            .extend(exceptions.launch_args.iter().cloned());
+        rules
+            .allowed_env
+            .extend(exceptions.allowed_env.iter().cloned());
+    }
+    rules
+}
+
+/**
+ * Whether a game may set an environment variable, according to the rules for the game
+ */
+fn is_env_allowed(rules: &SandboxPolicy, name: &str) -> bool {
+    let valid_name = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid_name
+        && !name.starts_with(BACKEND_ENV_PREFIX)
+        && rules
+            .allowed_env
+            .iter()
+            .any(|allowed| match allowed.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == allowed,
+            })
+}
+
+/**
+ * Check the environment a game asks to be launched with against the rules for the game (see
+ * [`for_game`]), leaving out everything that isn't allowed. Arguments are passed to the game
+ * after its app ID, where `flatpak run` doesn't read options, so they are all kept.
+ */
+#[must_use]
+pub fn launch_options(
+    rules: &SandboxPolicy,
+    game_id: &str,
+    options: LaunchOptions,
+) -> LaunchOptions {
+    let env = options
+        .env
+        .into_iter()
+        .filter(|(name, _)| {
+            let allowed = is_env_allowed(rules, name);
+            if !allowed {
+                log::warn!("Not setting {name} for {game_id}, the sandbox policy doesn't allow it");
+            }
+            allowed
+        })
+        .collect();
+    LaunchOptions {
+        args: options.args,
+        env,
+    }
+}
+
+/**
+ * Get the permissions a flatpak asks for from the `[Context]` group of its metadata, as each realm
+ * with its entries.
+ *
+ * # Errors
+ * This function will return an error if the metadata can't be read.
+ */
+pub fn context(metadata: &KeyFile) -> Result<Vec<(String, Vec<String>)>, Error> {
+    let mut context = Vec::new();
+    if !metadata.has_group("Context") {
+        return Ok(context);
+    }
+    for realm in metadata.keys("Context")?.iter().map(|entry| entry.to_str()) {
+        let permissions = metadata
+            .string_list("Context", realm)?
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect();
+        context.push((realm.to_string(), permissions));
+    }
+    Ok(context)
+}
+
+/**
+ * Check each permission a flatpak asks for (see [`context`]) against the rules for a game (see
+ * [`for_game`]).
+ */
+pub fn evaluate<I>(rules: &SandboxPolicy, context: I) -> Vec<PermissionCheck>
+where
+    I: IntoIterator<Item = (String, Vec<String>)>,
+{
+    let mut checks = Vec::new();
+    for (realm, permissions) in context {
+        // Listing an unknown realm is refused even without entries, as the realm's mere presence
+        // can change what flatpak gives the sandbox
+        if permissions.is_empty()
+            && rules.deny_unknown_realms
+            && !rules.allowed.contains_key(&realm)
+        {
+            checks.push(PermissionCheck {
+                explanation: format!("{realm} is not a known realm, and unknown realms are denied"),
+                realm,
+                permission: String::new(),
+                allowed: false,
+            });
+            continue;
+        }
+        for permission in permissions {
+            let (allowed, explanation) = match rules.allowed.get(&realm) {
+                Some(allowed) if allowed.contains(&permission) => {
+                    (true, format!("{realm} allows {permission}"))
+                }
+                Some(allowed) => (
+                    false,
+                    format!(
+                        "{permission} is not one of the allowed {realm}: {}",
+                        allowed.iter().cloned().collect::<Vec<String>>().join(", ")
+                    ),
+                ),
+                None if rules.deny_unknown_realms => (
+                    false,
+                    format!("{realm} is not a known realm, and unknown realms are denied"),
+                ),
+                None => (
+                    true,
+                    format!("{realm} is not a known realm, and unknown realms are allowed"),
+                ),
+            };
+            checks.push(PermissionCheck {
+                realm: realm.clone(),
+                permission,
+                allowed,
+                explanation,
+            });
+        }
+    }
+    checks
+}
+
+/**
+ * Check the permissions a flatpak asks for against the rules for a game. Returns a description of
+ * everything that isn't allowed, so an empty list means the game may be installed.
+ */
+pub fn violations<I>(rules: &SandboxPolicy, context: I) -> Vec<String>
+where
+    I: IntoIterator<Item = (String, Vec<String>)>,
+{
+    evaluate(rules, context)
+        .into_iter()
+        .filter(|check| !check.allowed)
+        .map(|check| {
+            format!(
+                "Capability {}={} is not allowed: {}",
+                check.realm, check.permission, check.explanation
+            )
+        })
+        .collect()
+}
@@ -1,10 +1,13 @@
-use crate::command::handle;
+use crate::api;
+use crate::command::handle_game;
 use crate::servers::open_server;
+use crate::session;
 use anyhow::anyhow;
 use devcade_onboard_types::{Request, RequestBody, Response, ResponseBody};
 use futures_util::future;
 use std::sync::Arc;
 use tokio::io::{AsyncWriteExt, Lines, WriteHalf};
+use tokio::net::unix::UCred;
 use tokio::sync::Mutex;
 use tokio::task;
 
@@ -14,6 +17,12 @@ pub async fn main(command_pipe: &str) -> ! {
 
     open_server(
         command_pipe,
+        async |peer: UCred| {
+            let pid = peer
+                .pid()
+                .ok_or_else(|| anyhow!("the PID of the process isn't known"))?;
+            api::check_game_process(pid).await
+        },
         async move |mut lines: Lines<_>, writer: WriteHalf<_>| {
             let writer = Arc::new(Mutex::new(writer));
             let mut handles = vec![];
@@ -24,24 +33,33 @@ pub async fn main(command_pipe: &str) -> ! {
                 let writer = writer.clone();
 
                 handles.push(task::spawn(async move {
-                    let body: ResponseBody = match &command.body {
-                        RequestBody::Ping => {
-                            log::trace!("Handling command: {command}");
-                            handle(command.body).await
-                        }
-                        RequestBody::Save(_, _, _)
-                        | RequestBody::Load(_, _)
-                        | RequestBody::Flush
-                        | RequestBody::GetNfcTag(_)
-                        | RequestBody::GetNfcUser(_) => {
-                            log::debug!("Handling command: {command}");
-                            handle(command.body).await
+                    let body: ResponseBody = match session::authorize(command.token.as_deref()) {
+                        Err(err) => {
+                            log::warn!("Rejecting command from game socket: {command}: {err}");
+                            err.into()
                         }
-                        // Don't allow game save/load to (for example) download a game, launch a game,
-                        // etc. If games could launch other games, it would update the 'current game' in
-                        // crate::api and allow games to corrupt other games' save data (possibly
-                        // maliciously!)
-                        _ => anyhow!("Invalid command: {}", command).into(),
+                        Ok(game_id) => match &command.body {
+                            RequestBody::Ping | RequestBody::Heartbeat => {
+                                log::trace!("Handling command: {command}");
+                                handle_game(command.body, game_id.as_str()).await
+                            }
+                            RequestBody::Save(_, _, _)
+                            | RequestBody::Load(_, _)
+                            | RequestBody::Flush
+                            | RequestBody::ExitToMenu
+                            | RequestBody::Notify(_, _)
+                            | RequestBody::GetSessionInfo
+                            | RequestBody::GetNfcTag(_)
+                            | RequestBody::GetNfcUser(_) => {
+                                log::debug!("Handling command: {command}");
+                                handle_game(command.body, game_id.as_str()).await
+                            }
+                            // Don't allow game save/load to (for example) download a game, launch a game,
+                            // etc. If games could launch other games, it would update the 'current game' in
+                            // crate::api and allow games to corrupt other games' save data (possibly
+                            // maliciously!)
+                            _ => anyhow!("Invalid command: {}", command).into(),
+                        },
                     };
                     let response = Response {
                         request_id: command.request_id,
@@ -7,6 +7,7 @@ use std::future::Future;
 use std::process::{Command, Stdio};
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, BufReader, Lines, ReadHalf, WriteHalf};
+use tokio::net::unix::UCred;
 use tokio::net::{UnixListener, UnixStream};
 use tokio::task;
 use tokio::task::JoinError;
@@ -143,8 +144,15 @@ impl Default for ThreadHandles {
     }
 }
 
-pub async fn open_server<'a, T, U>(path: &str, handle_client: T) -> !
+/**
+ * Serve clients on a unix socket. `authorize` is given the credentials of each process that
+ * connects, and connections it returns an error for are logged and closed before anything is read
+ * from them.
+ */
+pub async fn open_server<'a, A, B, T, U>(path: &str, authorize: A, handle_client: T) -> !
 where
+    A: (Fn(UCred) -> B) + Send + Sync + 'static,
+    B: Future<Output = Result<(), anyhow::Error>> + Send + 'static,
     T: (Fn(Lines<BufReader<ReadHalf<UnixStream>>>, WriteHalf<UnixStream>) -> U)
         + Send
         + Sync
@@ -152,12 +160,31 @@ where
     U: Future<Output = Result<(), anyhow::Error>> + Send + Sync + 'a + 'static,
 {
     let listener = bind_listener(path).unwrap();
+    let authorize = Arc::new(authorize);
     let handle_client = Arc::new(handle_client);
 
     let mut handles = vec![];
     while let Ok((stream, _address)) = listener.accept().await {
+        // Read with SO_PEERCRED, so it is who connected rather than who is writing now
+        let peer = match stream.peer_cred() {
+            Ok(peer) => peer,
+            Err(err) => {
+                log::warn!("Rejecting connection to {path}, couldn't get its credentials: {err}");
+                continue;
+            }
+        };
+        let path = path.to_owned();
+        let authorize = authorize.clone();
         let handle_client = handle_client.clone();
         handles.push(task::spawn(async move {
+            if let Err(err) = authorize(peer).await {
+                log::warn!(
+                    "Rejecting connection to {path} from UID {} (PID {:?}): {err}",
+                    peer.uid(),
+                    peer.pid()
+                );
+                return;
+            }
             let (reader, writer) = tokio::io::split(stream);
             let reader = BufReader::new(reader);
 
@@ -1,10 +1,16 @@
 use crate::command::handle;
+use crate::env::onboard_users;
+use crate::events;
 use crate::servers::open_server;
+use anyhow::anyhow;
 use devcade_onboard_types::{Request, RequestBody, Response, ResponseBody};
 use futures_util::future;
 use log::{log, Level};
 use std::sync::Arc;
 use tokio::io::{AsyncWriteExt, Lines, WriteHalf};
+use tokio::net::unix::UCred;
+use tokio::net::UnixStream;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::Mutex;
 use tokio::task;
 
@@ -26,9 +32,20 @@ pub async fn main(command_pipe: &str) -> ! {
 
     open_server(
         command_pipe_path,
+        async |peer: UCred| {
+            if onboard_users().contains(&peer.uid()) {
+                Ok(())
+            } else {
+                Err(anyhow!(
+                    "user {} isn't allowed to control the backend",
+                    peer.uid()
+                ))
+            }
+        },
         async move |mut lines: Lines<_>, writer: WriteHalf<_>| {
             let writer = Arc::new(Mutex::new(writer));
             let mut handles = vec![];
+            let mut subscriptions = vec![];
             while let Some(line) = lines.next_line().await? {
                 log::trace!("Received onboard command: {line}");
                 let command: Request = serde_json::from_str(&line)?;
@@ -41,6 +58,11 @@ pub async fn main(command_pipe: &str) -> ! {
 
                 let writer = writer.clone();
 
+                if let RequestBody::SubscribeEvents = &command.body {
+                    subscriptions.push(task::spawn(forward_events(command.request_id, writer)));
+                    continue;
+                }
+
                 handles.push(task::spawn(async move {
                     let body = handle(command.body).await;
                     let response = Response {
@@ -59,9 +81,42 @@ pub async fn main(command_pipe: &str) -> ! {
                     Ok(()) as Result<(), anyhow::Error>
                 }));
             }
+            // Nobody is left to receive events once the frontend disconnects
+            subscriptions
+                .iter()
+                .for_each(|subscription| subscription.abort());
             future::join_all(handles).await;
             Ok(())
         },
     )
     .await
 }
+
+/**
+ * Send every backend event to the frontend as a response to its `SubscribeEvents` request, until
+ * the frontend disconnects.
+ */
+async fn forward_events(
+    request_id: u32,
+    writer: Arc<Mutex<WriteHalf<UnixStream>>>,
+) -> Result<(), anyhow::Error> {
+    let mut events = events::subscribe();
+    let mut body = ResponseBody::Ok;
+    loop {
+        let response = Response { request_id, body };
+        log::debug!("Sending: {response}");
+        let mut response = serde_json::to_vec(&response)?;
+        response.push(b'\n');
+        writer.lock().await.write_all(&response).await?;
+
+        body = loop {
+            match events.recv().await {
+                Ok(event) => break ResponseBody::Event(event),
+                Err(RecvError::Lagged(missed)) => {
+                    log::warn!("Frontend fell behind and missed {missed} events");
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            }
+        };
+    }
+}
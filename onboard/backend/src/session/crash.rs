@@ -0,0 +1,213 @@
+use super::{logs, Session};
+use crate::api::write_atomic;
+use crate::env::{crash_threshold, games_path, state_path};
+use anyhow::Error;
+use devcade_onboard_types::{schema::DevcadeGame, ExitKind};
+use serde::{Deserialize, Serialize};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::UNIX_EPOCH;
+use tokio::fs;
+
+/**
+ * Name of the file in a game's directory that counts how often its installed version crashed
+ */
+const CRASHES_JSON: &str = "crashes.json";
+
+/**
+ * Directory in the state directory that crash reports are kept in, by game ID
+ */
+pub const CRASHES_DIR: &str = "crashes";
+
+/**
+ * How much of the end of a game's log is copied into its crash reports
+ */
+const LOG_TAIL_BYTES: usize = 16 * 1024;
+
+/**
+ * How many crash reports are kept for each game
+ */
+const REPORTS_KEPT: usize = 20;
+
+/**
+ * How many times in a row a version of a game has crashed
+ */
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct CrashCount {
+    hash: String,
+    consecutive: u32,
+}
+
+/**
+ * Everything known about a crashed game session, written to
+ * `$DEVCADE_PATH/state/<environment>/crashes/<game id>/<session id>.json`
+ */
+#[derive(Serialize, Debug)]
+pub struct CrashReport {
+    pub session_id: u64,
+    pub game_id: String,
+    pub game_name: Option<String>,
+    pub hash: Option<String>,
+    /// When the session started, in seconds since the unix epoch
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub exit: ExitKind,
+    pub consecutive_crashes: u32,
+    pub broken: bool,
+    pub log_tail: String,
+}
+
+/**
+ * Work out how a game's process ended from its exit status
+ */
+#[must_use]
+pub fn classify(status: ExitStatus) -> ExitKind {
+    match (status.code(), status.signal()) {
+        (Some(0), _) => ExitKind::Clean,
+        (Some(code), _) => ExitKind::NonZero(code),
+        (None, Some(signal)) => ExitKind::Signal(signal),
+        (None, None) => ExitKind::LaunchFailure(format!("Unknown exit status {status}")),
+    }
+}
+
+fn crashes_path(game_id: &str) -> PathBuf {
+    Path::new(games_path().as_str())
+        .join(game_id)
+        .join(CRASHES_JSON)
+}
+
+fn reports_dir(game_id: &str) -> PathBuf {
+    Path::new(state_path().as_str())
+        .join(CRASHES_DIR)
+        .join(game_id)
+}
+
+fn read_count(game_id: &str) -> Option<CrashCount> {
+    let json = std::fs::read_to_string(crashes_path(game_id)).ok()?;
+    serde_json::from_str(json.as_str()).ok()
+}
+
+/**
+ * Whether the given version of a game has crashed `crash_threshold` times in a row. A game stops
+ * being broken as soon as it is updated, since the count is kept per version.
+ */
+#[must_use]
+pub fn is_broken(game: &DevcadeGame) -> bool {
+    match (read_count(game.id.as_str()), game.hash.as_ref()) {
+        (Some(count), Some(hash)) => count.hash == *hash && count.consecutive >= crash_threshold(),
+        _ => false,
+    }
+}
+
+/**
+ * Reset the crash count of a game after one of its sessions ended cleanly
+ */
+pub async fn record_clean_exit(game: &DevcadeGame) {
+    if read_count(game.id.as_str()).is_none_or(|count| count.consecutive == 0) {
+        return;
+    }
+    if let Err(err) = fs::remove_file(crashes_path(game.id.as_str())).await {
+        log::warn!("Failed to reset crash count of {}: {err}", game.id);
+    }
+}
+
+/**
+ * Count a crash against the version of the game that was played, if it is known, and return how
+ * many times in a row that version has crashed.
+ */
+async fn count_crash(game: Option<&DevcadeGame>) -> Result<u32, Error> {
+    let Some((game_id, hash)) = game.and_then(|game| Some((game.id.as_str(), game.hash.clone()?)))
+    else {
+        return Ok(0);
+    };
+    let mut count = read_count(game_id)
+        .filter(|count| count.hash == hash)
+        .unwrap_or(CrashCount {
+            hash,
+            consecutive: 0,
+        });
+    count.consecutive += 1;
+    write_atomic(
+        &crashes_path(game_id),
+        serde_json::to_string(&count)?.as_bytes(),
+    )
+    .await?;
+    Ok(count.consecutive)
+}
+
+/**
+ * Record a crashed session: count it against the game's version and write a crash report. Returns
+ * how many times in a row the version has crashed and whether it is now considered broken.
+ */
+pub async fn record_crash(session: &Session, exit: ExitKind) -> (u32, bool) {
+    let consecutive_crashes = match count_crash(session.game.as_ref()).await {
+        Ok(count) => count,
+        Err(err) => {
+            log::warn!("Failed to count crash of {}: {err}", session.game_id);
+            0
+        }
+    };
+    let broken = consecutive_crashes >= crash_threshold();
+    if broken {
+        log::error!(
+            "Game {} has crashed {consecutive_crashes} times in a row, marking it broken",
+            session.game_id
+        );
+    }
+
+    let report = CrashReport {
+        session_id: session.id,
+        game_id: session.game_id.clone(),
+        game_name: session.game.as_ref().map(|game| game.name.clone()),
+        hash: session.game.as_ref().and_then(|game| game.hash.clone()),
+        started_at: session
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        duration_ms: session.duration().as_millis() as u64,
+        exit,
+        consecutive_crashes,
+        broken,
+        log_tail: logs::tail(session.game_id.as_str(), session.id, LOG_TAIL_BYTES).await,
+    };
+    if let Err(err) = write_report(&report).await {
+        log::warn!(
+            "Failed to write crash report for session {}: {err}",
+            session.id
+        );
+    }
+
+    (consecutive_crashes, broken)
+}
+
+async fn write_report(report: &CrashReport) -> Result<(), Error> {
+    let dir = reports_dir(report.game_id.as_str());
+    fs::create_dir_all(&dir).await?;
+    write_atomic(
+        &dir.join(format!("{}.json", report.session_id)),
+        serde_json::to_string_pretty(report)?.as_bytes(),
+    )
+    .await?;
+
+    // Only keep the most recent reports
+    let mut reports = Vec::new();
+    let mut entries = fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name();
+        let session = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".json"))
+            .and_then(|session| session.parse::<u64>().ok());
+        if let Some(session) = session {
+            reports.push(session);
+        }
+    }
+    reports.sort_unstable();
+    let excess = reports.len().saturating_sub(REPORTS_KEPT);
+    for session in reports.into_iter().take(excess) {
+        let _ = fs::remove_file(dir.join(format!("{session}.json"))).await;
+    }
+    Ok(())
+}
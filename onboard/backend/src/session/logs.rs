@@ -0,0 +1,249 @@
+use crate::env::{game_log_max_bytes, game_logs_kept, state_path};
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::{schema::DevcadeGame, GameLog};
+use futures_util::future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+/**
+ * Directory in the state directory that session logs are kept in, by game ID
+ */
+pub const LOGS_DIR: &str = "logs";
+
+/**
+ * Get the directory a game's session logs are kept in, for the current API environment
+ */
+#[must_use]
+pub fn logs_dir(game_id: &str) -> PathBuf {
+    Path::new(state_path().as_str())
+        .join(LOGS_DIR)
+        .join(game_id)
+}
+
+/**
+ * Get the path of the log of a game session. When the log grows past half of
+ * `game_log_max_bytes`, it is moved to `rotated_log_path` and a new one is started.
+ */
+#[must_use]
+pub fn log_path(game_id: &str, session_id: u64) -> PathBuf {
+    logs_dir(game_id).join(format!("{session_id}.log"))
+}
+
+fn rotated_log_path(game_id: &str, session_id: u64) -> PathBuf {
+    logs_dir(game_id).join(format!("{session_id}.log.old"))
+}
+
+/**
+ * The log file of a game session that is being written to
+ */
+struct LogFile {
+    game_id: String,
+    session_id: u64,
+    file: Option<fs::File>,
+    written: u64,
+}
+
+impl LogFile {
+    async fn open(game_id: &str, session_id: u64) -> Result<fs::File, Error> {
+        Ok(fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path(game_id, session_id))
+            .await?)
+    }
+
+    async fn write(&mut self, bytes: &[u8]) {
+        if self.file.is_none() {
+            return;
+        }
+        if self.written + bytes.len() as u64 > game_log_max_bytes() / 2 {
+            self.rotate().await;
+        }
+        if let Some(file) = self.file.as_mut() {
+            match file.write_all(bytes).await {
+                Ok(()) => self.written += bytes.len() as u64,
+                Err(err) => {
+                    log::warn!("Failed to write game log, discarding the rest: {err}");
+                    self.file = None;
+                }
+            }
+        }
+    }
+
+    /**
+     * Replace the previous half of the log with the current one, so the log never takes up more
+     * than `game_log_max_bytes` but always has the most recent output.
+     */
+    async fn rotate(&mut self) {
+        let path = log_path(&self.game_id, self.session_id);
+        let rotated = rotated_log_path(&self.game_id, self.session_id);
+        self.file = match fs::rename(&path, &rotated).await {
+            Ok(()) => LogFile::open(&self.game_id, self.session_id).await.ok(),
+            Err(err) => {
+                log::warn!("Failed to rotate game log, discarding the rest: {err}");
+                None
+            }
+        };
+        self.written = 0;
+    }
+}
+
+/**
+ * Capture the output of a game session into its log file. The returned handle finishes once both
+ * streams have been closed, which happens when the game exits. If the log can't be written, the
+ * output is still read and discarded so the game never blocks on a full pipe.
+ */
+pub async fn capture<O, E>(
+    game: &DevcadeGame,
+    session_id: u64,
+    stdout: Option<O>,
+    stderr: Option<E>,
+) -> JoinHandle<()>
+where
+    O: AsyncRead + Unpin + Send + 'static,
+    E: AsyncRead + Unpin + Send + 'static,
+{
+    prune(game.id.as_str()).await;
+
+    let file = match fs::create_dir_all(logs_dir(game.id.as_str())).await {
+        Ok(()) => LogFile::open(game.id.as_str(), session_id).await,
+        Err(err) => Err(err.into()),
+    };
+    let file = match file {
+        Ok(file) => Some(file),
+        Err(err) => {
+            log::warn!("Couldn't create log for session {session_id}: {err}");
+            None
+        }
+    };
+    let mut log = LogFile {
+        game_id: game.id.clone(),
+        session_id,
+        file,
+        written: 0,
+    };
+    let header = format!(
+        "--- Session {session_id} of {} ({}, version {}) started at {:?} ---\n",
+        game.name,
+        game.id,
+        game.hash.as_deref().unwrap_or("unknown"),
+        SystemTime::now()
+    );
+    log.write(header.as_bytes()).await;
+
+    let log = Arc::new(Mutex::new(log));
+    tokio::spawn(async move {
+        future::join(pipe(stdout, log.clone()), pipe(stderr, log.clone())).await;
+        if let Some(file) = log.lock().await.file.as_mut() {
+            let _ = file.flush().await;
+        }
+    })
+}
+
+/**
+ * Copy everything from one of a game's output streams into its log
+ */
+async fn pipe<R: AsyncRead + Unpin>(stream: Option<R>, log: Arc<Mutex<LogFile>>) {
+    let Some(mut stream) = stream else {
+        return;
+    };
+    let mut buffer = vec![0; 8192];
+    loop {
+        match stream.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(read) => log.lock().await.write(&buffer[..read]).await,
+            Err(err) => {
+                log::warn!("Failed to read game output: {err}");
+                break;
+            }
+        }
+    }
+}
+
+/**
+ * List the sessions a game has logs for, oldest first
+ */
+async fn sessions(game_id: &str) -> Vec<u64> {
+    let mut sessions = Vec::new();
+    let Ok(mut entries) = fs::read_dir(logs_dir(game_id)).await else {
+        return sessions;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file_name = entry.file_name();
+        let session = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".log"))
+            .and_then(|session| session.parse::<u64>().ok());
+        if let Some(session) = session {
+            sessions.push(session);
+        }
+    }
+    sessions.sort_unstable();
+    sessions
+}
+
+/**
+ * Delete the oldest logs of a game so that a new session's log can be added without keeping more
+ * than `game_logs_kept` logs.
+ */
+async fn prune(game_id: &str) {
+    let sessions = sessions(game_id).await;
+    let excess = (sessions.len() + 1).saturating_sub(game_logs_kept());
+    for session in sessions.into_iter().take(excess) {
+        log::debug!("Removing old log for session {session} of {game_id}");
+        let _ = fs::remove_file(log_path(game_id, session)).await;
+        let _ = fs::remove_file(rotated_log_path(game_id, session)).await;
+    }
+}
+
+/**
+ * Read the log of a game session. If `session_id` is `None`, the log of the game's most recent
+ * session is read.
+ *
+ * # Errors
+ * This function will return an error if the game ID isn't valid, if there is no log for the
+ * session, or if it cannot be read.
+ */
+pub async fn read(game_id: &str, session_id: Option<u64>) -> Result<GameLog, Error> {
+    crate::api::validate_game_id(game_id)?;
+    let sessions = sessions(game_id).await;
+    let session_id = match session_id {
+        Some(session_id) if sessions.contains(&session_id) => session_id,
+        Some(session_id) => {
+            return Err(anyhow!("No log for session {session_id} of game {game_id}"));
+        }
+        None => *sessions
+            .last()
+            .ok_or_else(|| anyhow!("Game {game_id} has no logs"))?,
+    };
+
+    let mut contents = fs::read(rotated_log_path(game_id, session_id))
+        .await
+        .unwrap_or_default();
+    contents.extend(fs::read(log_path(game_id, session_id)).await?);
+    Ok(GameLog {
+        game_id: game_id.to_string(),
+        session_id,
+        contents: String::from_utf8_lossy(&contents).into_owned(),
+        sessions,
+    })
+}
+
+/**
+ * Get the last `max_bytes` of the log of a game session, or an empty string if it has no log.
+ */
+pub async fn tail(game_id: &str, session_id: u64, max_bytes: usize) -> String {
+    let Ok(log) = read(game_id, Some(session_id)).await else {
+        return String::new();
+    };
+    let mut start = log.contents.len().saturating_sub(max_bytes);
+    while !log.contents.is_char_boundary(start) {
+        start += 1;
+    }
+    log.contents[start..].to_string()
+}
@@ -0,0 +1,487 @@
+/**
+ * Module for capturing the output of games into per-session log files
+ */
+pub mod logs;
+
+/**
+ * Module for classifying how games exit, writing crash reports and marking broken games
+ */
+pub mod crash;
+
+/**
+ * Module for ending sessions that run for too long or stop receiving heartbeats
+ */
+pub mod watchdog;
+
+/**
+ * Module for keeping track of how much each game is played
+ */
+pub mod stats;
+
+/**
+ * Module for relaying notifications from games to the frontend without letting them spam it
+ */
+pub mod notify;
+
+use crate::api::launcher::GameLauncher;
+use crate::events;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::{
+    schema::DevcadeGame, Event, ExitKind, Player, SessionInfo, SessionState, SignedInPlayer,
+};
+use lazy_static::lazy_static;
+use std::fs::File;
+use std::io::Read;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * A game that has been launched, from the moment the frontend asks for it until it exits
+ */
+#[derive(Clone, Debug)]
+pub struct Session {
+    /**
+     * Unique ID of the session. IDs are based on the time the session started so they stay
+     * unique across backend restarts.
+     */
+    pub id: u64,
+
+    /**
+     * ID of the game being played
+     */
+    pub game_id: String,
+
+    /**
+     * The game being played. This is `None` while the game is still being downloaded.
+     */
+    pub game: Option<DevcadeGame>,
+
+    /**
+     * PID of the process the game was launched with. This is `None` until the game is running.
+     */
+    pub pid: Option<u32>,
+
+    /**
+     * When the session started
+     */
+    pub started_at: SystemTime,
+
+    /**
+     * When the game started running, after it was downloaded. This is `None` until then.
+     */
+    pub running_since: Option<SystemTime>,
+
+    /**
+     * When the game was paused, if it is paused right now
+     */
+    pub paused_since: Option<SystemTime>,
+
+    /**
+     * How long the game has been paused for in total, not counting the current pause
+     */
+    pub paused_for: Duration,
+
+    /**
+     * What the session is currently doing
+     */
+    pub state: SessionState,
+
+    /**
+     * When the frontend or the game last sent a heartbeat, or when the session started if neither
+     * has yet
+     */
+    pub last_activity: SystemTime,
+
+    /**
+     * Whether the game is being stopped on purpose, so it exiting isn't a crash
+     */
+    pub stopping: bool,
+
+    /**
+     * The last resource limit the game ran into, if any
+     */
+    pub limit_exceeded: Option<String>,
+
+    /**
+     * Whether the game is being shown as a demo by attract mode, rather than played
+     */
+    pub demo: bool,
+
+    /**
+     * Players that signed in with their NFC tag during the session
+     */
+    pub players: Vec<SignedInPlayer>,
+
+    /**
+     * Secret the game has to send with every request on the game socket. It is revoked when the
+     * session ends, so nothing the game leaves behind can act as a later game.
+     */
+    pub token: String,
+
+    /**
+     * Launcher the game was launched with. Pausing and stopping the game goes through it, so
+     * they act on the installation the game runs from even if the API environment changes.
+     */
+    pub launcher: Arc<dyn GameLauncher>,
+}
+
+impl Session {
+    /**
+     * How long the session has been going on for
+     */
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.started_at.elapsed().unwrap_or_default()
+    }
+
+    /**
+     * How long the game has been played for in this session, leaving out the time it was paused
+     */
+    #[must_use]
+    pub fn play_time(&self) -> Duration {
+        let running = self
+            .running_since
+            .and_then(|since| since.elapsed().ok())
+            .unwrap_or_default();
+        let paused = self.paused_for
+            + self
+                .paused_since
+                .and_then(|since| since.elapsed().ok())
+                .unwrap_or_default();
+        running.saturating_sub(paused)
+    }
+
+    /**
+     * Get the information about this session that is sent to the frontend
+     */
+    #[must_use]
+    pub fn info(&self) -> SessionInfo {
+        SessionInfo {
+            session_id: self.id,
+            game_id: self.game_id.clone(),
+            started_at: self
+                .started_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            state: self.state.clone(),
+            demo: self.demo,
+        }
+    }
+}
+
+lazy_static! {
+    static ref CURRENT_SESSION: Mutex<Option<Session>> = Mutex::new(None);
+}
+
+static LAST_SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+/**
+ * Generate a new session ID. This is the current time in milliseconds, bumped if needed so that
+ * two sessions never get the same ID.
+ */
+fn next_session_id() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let previous = LAST_SESSION_ID
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or_default();
+    now.max(previous + 1)
+}
+
+/**
+ * Generate a random token for a session
+ */
+fn generate_token() -> Result<String, Error> {
+    let mut bytes = [0u8; 32];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/**
+ * Get the current session, if a game is being launched or played
+ */
+#[must_use]
+pub fn current() -> Option<Session> {
+    CURRENT_SESSION.lock().unwrap().clone()
+}
+
+/**
+ * Check that a request on the game socket was sent by the game of the current session. Returns
+ * the ID of that game, which the request has to be attributed to even if the session ends while
+ * it is handled.
+ *
+ * # Errors
+ * This function will return an error if there is no session, or if `token` isn't the token of the
+ * current session.
+ */
+pub fn authorize(token: Option<&str>) -> Result<String, Error> {
+    let token = token.ok_or_else(|| anyhow!("Requests to the game socket need a session token"))?;
+    let current = CURRENT_SESSION.lock().unwrap();
+    match current.as_ref() {
+        Some(session) if session.token == token => Ok(session.game_id.clone()),
+        Some(_) => Err(anyhow!("Session token isn't valid (anymore)")),
+        None => Err(anyhow!("There isn't a game running right now")),
+    }
+}
+
+/**
+ * Get the game that is currently being played, if there is one
+ */
+#[must_use]
+pub fn current_game() -> Option<DevcadeGame> {
+    current().and_then(|session| session.game)
+}
+
+/**
+ * Start a new session for a game that is about to be launched, or shown as a demo if `demo` is
+ * set. The game is run with `launcher` for the whole session.
+ *
+ * # Errors
+ * This function will return an error if another session is still going on.
+ */
+pub fn begin(game_id: &str, demo: bool, launcher: Arc<dyn GameLauncher>) -> Result<u64, Error> {
+    let mut current = CURRENT_SESSION.lock().unwrap();
+    if let Some(session) = current.as_ref() {
+        return Err(anyhow!(
+            "Can't launch {game_id}, game {} is still running (session {})",
+            session.game_id,
+            session.id
+        ));
+    }
+    let token =
+        generate_token().map_err(|err| anyhow!("Couldn't generate session token: {err}"))?;
+    let session = Session {
+        id: next_session_id(),
+        game_id: game_id.to_string(),
+        game: None,
+        pid: None,
+        started_at: SystemTime::now(),
+        running_since: None,
+        paused_since: None,
+        paused_for: Duration::ZERO,
+        state: SessionState::Starting,
+        last_activity: SystemTime::now(),
+        stopping: false,
+        limit_exceeded: None,
+        demo,
+        players: Vec::new(),
+        token,
+        launcher,
+    };
+    log::info!("Starting session {} for game {game_id}", session.id);
+    events::emit(Event::GameStarting {
+        session_id: session.id,
+        game_id: session.game_id.clone(),
+        demo,
+    });
+    let id = session.id;
+    *current = Some(session);
+    Ok(id)
+}
+
+/**
+ * Record that the game of a session has been spawned and is now running.
+ */
+pub fn running(session_id: u64, game: DevcadeGame, pid: u32) {
+    let mut current = CURRENT_SESSION.lock().unwrap();
+    let Some(session) = current.as_mut().filter(|session| session.id == session_id) else {
+        log::warn!("Session {session_id} started running, but it isn't the current session");
+        return;
+    };
+    session.game = Some(game);
+    session.pid = Some(pid);
+    session.running_since = Some(SystemTime::now());
+    session.state = SessionState::Running;
+    events::emit(Event::GameRunning {
+        session_id,
+        game_id: session.game_id.clone(),
+    });
+    // Demos aren't played, so they don't count towards how much a game is played
+    if !session.demo {
+        tokio::spawn(stats::record_launch(session.game_id.clone()));
+    }
+    tokio::spawn(watchdog::watch(session_id));
+}
+
+/**
+ * Record that the game of a session has been paused or resumed.
+ */
+pub fn set_paused(session_id: u64, paused: bool) {
+    let mut current = CURRENT_SESSION.lock().unwrap();
+    let Some(session) = current.as_mut().filter(|session| session.id == session_id) else {
+        return;
+    };
+    if paused {
+        session.state = SessionState::Paused;
+        session.paused_since.get_or_insert_with(SystemTime::now);
+    } else {
+        session.state = SessionState::Running;
+        if let Some(since) = session.paused_since.take() {
+            session.paused_for += since.elapsed().unwrap_or_default();
+        }
+        // Time spent paused doesn't count towards the idle timeout
+        session.last_activity = SystemTime::now();
+    }
+}
+
+/**
+ * Record that the game of a session ran into one of its resource limits, so that if it crashes,
+ * the limit is reported as the reason.
+ */
+pub fn limit_exceeded(session_id: u64, reason: &str) {
+    let mut current = CURRENT_SESSION.lock().unwrap();
+    if let Some(session) = current.as_mut().filter(|session| session.id == session_id) {
+        session.limit_exceeded = Some(reason.to_string());
+    }
+}
+
+/**
+ * Record that a player signed in to the current session with their NFC tag. `handle` replaces
+ * whoever was signed in at the same controls before.
+ */
+pub fn signed_in(player: Player, handle: String) {
+    let mut current = CURRENT_SESSION.lock().unwrap();
+    let Some(session) = current.as_mut() else {
+        return;
+    };
+    session
+        .players
+        .retain(|signed_in| signed_in.player != player);
+    session.players.push(SignedInPlayer { player, handle });
+}
+
+/**
+ * Record activity in the current session, resetting its idle timeout. Returns whether there is a
+ * session.
+ */
+pub fn heartbeat() -> bool {
+    let mut current = CURRENT_SESSION.lock().unwrap();
+    match current.as_mut() {
+        Some(session) => {
+            session.last_activity = SystemTime::now();
+            true
+        }
+        None => false,
+    }
+}
+
+/**
+ * Record that the game of a session is about to be killed, so it exiting is reported as a normal
+ * exit instead of a crash.
+ */
+pub fn stopping(session_id: u64) {
+    let mut current = CURRENT_SESSION.lock().unwrap();
+    if let Some(session) = current.as_mut().filter(|session| session.id == session_id) {
+        session.stopping = true;
+    }
+}
+
+/**
+ * Record that the game of a session asked to exit to the menu, so it is about to be stopped.
+ * Returns whether the session wasn't being stopped already.
+ */
+pub fn exit_requested(session_id: u64) -> bool {
+    let mut current = CURRENT_SESSION.lock().unwrap();
+    let Some(session) = current
+        .as_mut()
+        .filter(|session| session.id == session_id && !session.stopping)
+    else {
+        return false;
+    };
+    session.stopping = true;
+    log::info!("Game {} asked to exit to the menu", session.game_id);
+    events::emit(Event::GameExitRequested {
+        session_id,
+        game_id: session.game_id.clone(),
+    });
+    true
+}
+
+/**
+ * End a session whose game couldn't be launched.
+ */
+pub async fn launch_failed(session_id: u64, error: &Error) {
+    let Some(session) = take(session_id) else {
+        return;
+    };
+    log::error!("Failed to launch game {}: {error}", session.game_id);
+    crashed(session, ExitKind::LaunchFailure(error.to_string())).await;
+}
+
+/**
+ * End a session whose game has exited.
+ */
+pub async fn exited(session_id: u64, status: ExitStatus) {
+    let Some(session) = take(session_id) else {
+        return;
+    };
+    let duration_ms = session.duration().as_millis() as u64;
+    let exit = match (crash::classify(status), session.limit_exceeded.clone()) {
+        (ExitKind::Clean, _) => ExitKind::Clean,
+        (_, Some(reason)) => ExitKind::ResourceLimit(reason),
+        (exit, None) => exit,
+    };
+    if exit != ExitKind::Clean && !session.stopping {
+        log::warn!(
+            "Game {} crashed after {duration_ms}ms ({exit})",
+            session.game_id
+        );
+        crashed(session, exit).await;
+        return;
+    }
+
+    log::info!("Game {} exited after {duration_ms}ms", session.game_id);
+    if !session.demo {
+        stats::record_session(session.game_id.as_str(), session.play_time(), false).await;
+    }
+    if let Some(game) = session.game.as_ref().filter(|_| exit == ExitKind::Clean) {
+        crash::record_clean_exit(game).await;
+    }
+    events::emit(Event::GameExited {
+        session_id,
+        game_id: session.game_id.clone(),
+        exit_code: status.code(),
+        duration_ms,
+    });
+}
+
+/**
+ * Report a session that ended in a crash
+ */
+async fn crashed(session: Session, exit: ExitKind) {
+    let (consecutive_crashes, broken) = crash::record_crash(&session, exit.clone()).await;
+    // Games that failed to launch were never played, so they don't count as a launch either
+    if session.running_since.is_some() && !session.demo {
+        stats::record_session(session.game_id.as_str(), session.play_time(), true).await;
+    }
+    events::emit(Event::GameCrashed {
+        session_id: session.id,
+        game_id: session.game_id.clone(),
+        reason: exit.to_string(),
+        exit,
+        duration_ms: session.duration().as_millis() as u64,
+        consecutive_crashes,
+        broken,
+    });
+}
+
+/**
+ * Remove a session if it is the current one.
+ */
+fn take(session_id: u64) -> Option<Session> {
+    let mut current = CURRENT_SESSION.lock().unwrap();
+    match current.as_ref() {
+        Some(session) if session.id == session_id => current.take(),
+        _ => {
+            log::warn!("Session {session_id} ended, but it isn't the current session");
+            None
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::events;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::{Event, Severity};
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/**
+ * How many notifications a game can show within `NOTIFICATION_WINDOW`
+ */
+const MAX_NOTIFICATIONS: usize = 3;
+
+/**
+ * How far back notifications count towards `MAX_NOTIFICATIONS`
+ */
+const NOTIFICATION_WINDOW: Duration = Duration::from_secs(10);
+
+/**
+ * How many characters of a notification are shown, the rest is cut off
+ */
+const MAX_LENGTH: usize = 200;
+
+lazy_static! {
+    /**
+     * The session that last sent a notification, and when it sent its recent notifications
+     */
+    static ref SENT: Mutex<(u64, VecDeque<Instant>)> = Mutex::new((0, VecDeque::new()));
+}
+
+/**
+ * Record a notification from a session, unless it has already sent too many recently.
+ */
+fn allow(session_id: u64) -> bool {
+    let mut sent = SENT.lock().unwrap();
+    let (last_session, times) = &mut *sent;
+    if *last_session != session_id {
+        *last_session = session_id;
+        times.clear();
+    }
+    let now = Instant::now();
+    while times
+        .front()
+        .is_some_and(|time| now.duration_since(*time) >= NOTIFICATION_WINDOW)
+    {
+        times.pop_front();
+    }
+    if times.len() >= MAX_NOTIFICATIONS {
+        return false;
+    }
+    times.push_back(now);
+    true
+}
+
+/**
+ * Show a notification from the game of the current session in the frontend's overlay. Control
+ * characters are removed and long text is cut off.
+ *
+ * # Errors
+ * This function will return an error if no game is running, if the text is empty, or if the game
+ * has sent too many notifications recently.
+ */
+pub fn notify(text: &str, severity: Severity) -> Result<(), Error> {
+    let session = super::current()
+        .filter(|session| session.running_since.is_some())
+        .ok_or_else(|| anyhow!("There isn't a game running right now"))?;
+    let text = text
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_LENGTH)
+        .collect::<String>();
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(anyhow!("Notifications can't be empty"));
+    }
+    if !allow(session.id) {
+        log::warn!(
+            "Dropping notification from game {}, it sent too many",
+            session.game_id
+        );
+        return Err(anyhow!(
+            "Too many notifications, at most {MAX_NOTIFICATIONS} can be shown every {}s",
+            NOTIFICATION_WINDOW.as_secs()
+        ));
+    }
+    events::emit(Event::GameNotification {
+        session_id: session.id,
+        game_id: session.game_id,
+        text: text.to_string(),
+        severity,
+    });
+    Ok(())
+}
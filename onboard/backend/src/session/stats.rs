@@ -0,0 +1,141 @@
+use crate::api::write_atomic;
+use crate::env::state_path;
+use anyhow::Error;
+use devcade_onboard_types::GameStats;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+/**
+ * Name of the file the statistics of every game are kept in, in the state directory
+ */
+pub const STATS_JSON: &str = "stats.json";
+
+lazy_static! {
+    /**
+     * Held while the statistics are being updated, so two updates can't overwrite each other
+     */
+    static ref STATS_LOCK: Mutex<()> = Mutex::new(());
+}
+
+fn stats_path() -> PathBuf {
+    Path::new(state_path().as_str()).join(STATS_JSON)
+}
+
+/**
+ * Read the statistics of every game, by game ID
+ */
+async fn read_all() -> BTreeMap<String, GameStats> {
+    let Ok(json) = fs::read_to_string(stats_path()).await else {
+        return BTreeMap::new();
+    };
+    match serde_json::from_str(json.as_str()) {
+        Ok(stats) => stats,
+        Err(err) => {
+            log::warn!("Play statistics can't be read, starting over: {err}");
+            BTreeMap::new()
+        }
+    }
+}
+
+/**
+ * Change the statistics of a game
+ */
+async fn update(game_id: &str, change: impl FnOnce(&mut GameStats)) -> Result<(), Error> {
+    let _lock = STATS_LOCK.lock().await;
+    let mut all = read_all().await;
+    let stats = all.entry(game_id.to_owned()).or_insert_with(|| GameStats {
+        game_id: game_id.to_owned(),
+        ..GameStats::default()
+    });
+    change(stats);
+    stats.average_play_ms = stats.total_play_ms / stats.launches.max(1);
+    fs::create_dir_all(state_path()).await?;
+    write_atomic(&stats_path(), serde_json::to_string(&all)?.as_bytes()).await
+}
+
+/**
+ * Count a launch of a game
+ */
+pub async fn record_launch(game_id: String) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let updated = update(game_id.as_str(), |stats| {
+        stats.launches += 1;
+        stats.last_played = Some(now);
+    })
+    .await;
+    if let Err(err) = updated {
+        log::warn!("Failed to count launch of {game_id}: {err}");
+    }
+}
+
+/**
+ * Add a session that ended to the statistics of its game
+ */
+pub async fn record_session(game_id: &str, played: Duration, crashed: bool) {
+    let updated = update(game_id, |stats| {
+        stats.total_play_ms += played.as_millis() as u64;
+        if crashed {
+            stats.crashes += 1;
+        }
+    })
+    .await;
+    if let Err(err) = updated {
+        log::warn!("Failed to record session of {game_id}: {err}");
+    }
+}
+
+/**
+ * Get the statistics of a game. Games that have never been played have empty statistics.
+ */
+pub async fn get(game_id: &str) -> GameStats {
+    read_all()
+        .await
+        .remove(game_id)
+        .unwrap_or_else(|| GameStats {
+            game_id: game_id.to_owned(),
+            ..GameStats::default()
+        })
+}
+
+/**
+ * Get the statistics of every game that has been played, most launched first
+ */
+pub async fn all() -> Vec<GameStats> {
+    let mut stats = read_all().await.into_values().collect::<Vec<GameStats>>();
+    stats.sort_by(|a, b| b.launches.cmp(&a.launches).then(a.game_id.cmp(&b.game_id)));
+    stats
+}
+
+/**
+ * Format statistics as CSV, with a header row
+ */
+#[must_use]
+pub fn to_csv(stats: &[GameStats]) -> String {
+    let mut csv =
+        String::from("game_id,launches,total_play_ms,average_play_ms,last_played,crashes\n");
+    for game in stats {
+        // Game IDs are used as directory names, but quote them in case they contain commas
+        csv.push_str(
+            format!(
+                "\"{}\",{},{},{},{},{}\n",
+                game.game_id.replace('"', "\"\""),
+                game.launches,
+                game.total_play_ms,
+                game.average_play_ms,
+                game.last_played
+                    .map(|time| time.to_string())
+                    .unwrap_or_default(),
+                game.crashes
+            )
+            .as_str(),
+        );
+    }
+    csv
+}
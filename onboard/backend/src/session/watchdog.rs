@@ -0,0 +1,89 @@
+use super::Session;
+use crate::env::{idle_timeout, max_session_length, watchdog_warning};
+use crate::events;
+use devcade_onboard_types::{Event, SessionState, WatchdogReason};
+use std::time::{Duration, SystemTime};
+
+/**
+ * How often the watchdog checks on a session
+ */
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/**
+ * Get the earliest time the watchdog should end a session at, and why
+ */
+fn deadline(
+    session: &Session,
+    max_length: Option<Duration>,
+    idle: Option<Duration>,
+) -> Option<(WatchdogReason, SystemTime)> {
+    let max_length =
+        max_length.map(|max| (WatchdogReason::MaxSessionLength, session.started_at + max));
+    // Nobody can play a paused game or a demo, so they can't be idle either
+    let idle = idle
+        .filter(|_| session.state != SessionState::Paused && !session.demo)
+        .map(|idle| (WatchdogReason::Idle, session.last_activity + idle));
+    [max_length, idle]
+        .into_iter()
+        .flatten()
+        .min_by_key(|(_, deadline)| *deadline)
+}
+
+/**
+ * Watch a running session until it ends. The player is warned `watchdog_warning` before the
+ * session runs out of time, and the game is killed once it does so the frontend goes back to the
+ * menu.
+ */
+pub async fn watch(session_id: u64) {
+    let max_length = max_session_length();
+    let idle = idle_timeout();
+    if max_length.is_none() && idle.is_none() {
+        return;
+    }
+    let warning = watchdog_warning();
+    let mut warned = None;
+    let mut terminated = false;
+
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        let Some(session) = super::current().filter(|session| session.id == session_id) else {
+            return;
+        };
+        // A paused game has no deadline until it is resumed, the session isn't over yet
+        let Some((reason, deadline)) = deadline(&session, max_length, idle) else {
+            continue;
+        };
+        let remaining = deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+
+        if remaining.is_zero() {
+            if !terminated {
+                log::warn!("Ending session {session_id}: {reason}");
+                events::emit(Event::SessionTerminated {
+                    session_id,
+                    game_id: session.game_id.clone(),
+                    reason,
+                });
+                terminated = true;
+            }
+            // Keep trying until the game is gone
+            match crate::api::kill_current_game().await {
+                Ok(_) => return,
+                Err(err) => log::error!("Watchdog failed to kill session {session_id}: {err}"),
+            }
+        } else if remaining <= warning && warned != Some((reason, deadline)) {
+            log::info!(
+                "Session {session_id} ends in {}s: {reason}",
+                remaining.as_secs()
+            );
+            events::emit(Event::SessionWarning {
+                session_id,
+                game_id: session.game_id.clone(),
+                reason,
+                remaining_secs: remaining.as_secs(),
+            });
+            warned = Some((reason, deadline));
+        }
+    }
+}
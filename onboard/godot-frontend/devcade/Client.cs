@@ -13,6 +13,7 @@ using System.Threading.Tasks;
 namespace onboard.devcade;
 
 using System.Runtime.CompilerServices;
+using Newtonsoft.Json.Linq;
 using util;
 
 public static class Client {
@@ -27,15 +28,24 @@ public static class Client {
     public static event EventHandler<DevcadeGame> onGameFinished = (_, _) => {
         LOG.Info("onGameFinished Invoked");
     };
+    /// <summary>
+    /// Invoked for every event the backend sends, e.g. a game starting or exiting. The dictionary contains the
+    /// event's "type" and its "data".
+    /// </summary>
+    public static event EventHandler<Dictionary<string, object>> onBackendEvent = (_, ev) => {
+        LOG.Info($"onBackendEvent Invoked ({ev["type"]})");
+    };
     
     /**
      * File System
      *   tmp/
-     *   |- {game.id}/
-     *       |- banner.png
-     *       |- icon.png
-     *       |- publish
-     *          |- {game.name} (executable)
+     *   |- development/ (same layout, for games from the development API)
+     *   |- production/
+     *       |- {game.id}/
+     *           |- banner.png
+     *           |- icon.png
+     *           |- publish
+     *              |- {game.name} (executable)
      */
 
     /// <summary>
@@ -43,6 +53,15 @@ public static class Client {
     /// </summary>
     public static bool isProduction { get; private set; } = true;
 
+    /// <summary>
+    /// Path to the directory the backend stores a game's files in. Development games are kept
+    /// separate from production games so the two never overwrite each other.
+    /// </summary>
+    /// <param name="id">The id of the game</param>
+    public static string gameDir(string id) {
+        return isProduction ? $"{workingDir}/production/{id}" : $"{workingDir}/development/{id}";
+    }
+
     /// <summary>
     /// True if a game is currently launched/running
     /// </summary>
@@ -73,6 +92,19 @@ public static class Client {
     /// </summary>
     private static readonly Dictionary<uint, TaskCompletionSource<Response>> tasks = new();
     /// <summary>
+    /// Tasks for launched games by session id, completed when the backend reports that the game has exited
+    /// </summary>
+    private static readonly Dictionary<ulong, TaskCompletionSource<Response>> sessions = new();
+    /// <summary>
+    /// Exit events for sessions that ended before their launch response was handled
+    /// </summary>
+    private static readonly Dictionary<ulong, Response> endedSessions = new();
+
+    /// <summary>
+    /// Sessions the backend started to show a demo in attract mode, which nobody waits for
+    /// </summary>
+    private static readonly HashSet<ulong> demoSessions = new();
+    /// <summary>
     /// List of requests to be sent to the backend. Only used when the backend is not connected yet.
     /// </summary>
     private static readonly List<Request> requests = new();
@@ -128,6 +160,9 @@ public static class Client {
 
         LOG.Info($"Opened read pipe: {workingDir}/onboard.sock");
 
+        // Sent once the backend answers the first ping
+        subscribeEvents();
+
         repeatPing(5000, 5000);
 
         // Start the main loop
@@ -153,6 +188,12 @@ public static class Client {
             // Parse the message
             Response res = Response.deserialize(message);
 
+            // Events all share the request id of the subscription, so they never complete a task
+            if (res.type == Response.ResponseType.Event) {
+                handleEvent(res);
+                continue;
+            }
+
             if (!tasks.ContainsKey(res.request_id)) {
                 LOG.Warn("Received response for unknown request id: " + res.request_id);
 
@@ -183,6 +224,12 @@ public static class Client {
                 case Response.ResponseType.Game:
                     LOG.Info($"Received game response for request {res.request_id}");
                     break;
+                case Response.ResponseType.SessionId:
+                    LOG.Info($"Received session id response for request {res.request_id}");
+                    break;
+                case Response.ResponseType.AssetPrefetch:
+                    LOG.Info($"Received asset prefetch response for request {res.request_id}");
+                    break;
                 case Response.ResponseType.GameList:
                     LOG.Info($"Received game list response for request {res.request_id} (contained {res.unwrap<List<DevcadeGame>>().Count} games)");
                     break;
@@ -251,6 +298,37 @@ public static class Client {
         writer.BaseStream.Flush();
     }
     
+    /// <summary>
+    /// Handles an event sent by the backend. Completes the task of a launched game once its session has ended.
+    /// </summary>
+    /// <param name="res">The event response</param>
+    private static void handleEvent(Response res) {
+        var ev = res.unwrap<Dictionary<string, object>>();
+        string type = (string)ev["type"];
+        LOG.Info($"Received {type} event");
+
+        if (type is "GameStarting" && (((JObject)ev["data"])["demo"]?.ToObject<bool>() ?? false)) {
+            lock (sessions) {
+                demoSessions.Add(((JObject)ev["data"])["session_id"]!.ToObject<ulong>());
+            }
+        }
+
+        if (type is "GameExited" or "GameCrashed") {
+            ulong session = ((JObject)ev["data"])["session_id"]!.ToObject<ulong>();
+            lock (sessions) {
+                if (demoSessions.Remove(session)) {
+                    // Nothing launched a demo, so nothing is waiting for it to end
+                } else if (sessions.Remove(session, out var tcs)) {
+                    tcs.SetResult(res);
+                } else {
+                    endedSessions[session] = res;
+                }
+            }
+        }
+
+        onBackendEvent.Invoke(null, ev);
+    }
+
     #endregion
     
     #region Request Methods
@@ -302,6 +380,17 @@ public static class Client {
             });
     }
     
+    /// <summary>
+    /// Sends a single request to the backend to download the icons and banners for all the given games.
+    /// The backend downloads them concurrently and reports which games failed.
+    /// </summary>
+    /// <param name="ids">The ids of the games to download artwork for</param>
+    /// <returns>A Task that will be completed once all the artwork has been downloaded</returns>
+    public static Task<Response> prefetchAssets(List<string> ids) {
+        LOG.Info($"Prefetching artwork for {ids.Count} games");
+        return sendRequest(Request.PrefetchAssets(ids));
+    }
+
     /// <summary>
     /// Sends a request to the backend to download the game with the given id.
     /// Once the game has been downloaded, invokes the onGameFinished event.
@@ -325,7 +414,30 @@ public static class Client {
     public static Task<Response> launchGame(string id) {
         LOG.Info($"Launching game with id {id}");
         gameLauched = true;
-        return sendRequest(Request.LaunchGame(id)).ContinueWith(task => {gameLauched = false; return task.Result;} );
+        return sendRequest(Request.LaunchGame(id)).ContinueWith(task => {
+            // The backend responds as soon as the game has started, wait for its exit event
+            if (task.Result.type != Response.ResponseType.SessionId) {
+                return Task.FromResult(task.Result);
+            }
+            ulong session = task.Result.unwrap<ulong>();
+            lock (sessions) {
+                if (endedSessions.Remove(session, out var ended)) {
+                    return Task.FromResult(ended);
+                }
+                TaskCompletionSource<Response> tcs = new();
+                sessions[session] = tcs;
+                return tcs.Task;
+            }
+        }).Unwrap().ContinueWith(task => {gameLauched = false; return task.Result;} );
+    }
+
+    /// <summary>
+    /// Sends a request to the backend to send all of its events (e.g. games starting and exiting) to this client.
+    /// </summary>
+    /// <returns>A Task that will be completed once the backend has subscribed this client</returns>
+    public static Task<Response> subscribeEvents() {
+        LOG.Info("Subscribing to backend events");
+        return sendRequest(Request.SubscribeEvents());
     }
 
     /// <summary>
@@ -337,6 +449,33 @@ public static class Client {
         return sendRequest(Request.KillGame());
     }
 
+    /// <summary>
+    /// Sends a request to the backend to freeze the running game, e.g. while an overlay is shown over it.
+    /// </summary>
+    /// <returns>A Task that will be completed when the backend has responded</returns>
+    public static Task<Response> pauseGame() {
+        LOG.Info($"Pausing game");
+        return sendRequest(Request.PauseGame());
+    }
+
+    /// <summary>
+    /// Sends a request to the backend to continue a game paused with <see cref="pauseGame"/>.
+    /// </summary>
+    /// <returns>A Task that will be completed when the backend has responded</returns>
+    public static Task<Response> resumeGame() {
+        LOG.Info($"Resuming game");
+        return sendRequest(Request.ResumeGame());
+    }
+
+    /// <summary>
+    /// Tells the backend that a player pressed something, which keeps the running game from being ended as
+    /// idle and ends a demo shown by attract mode.
+    /// </summary>
+    /// <returns>A Task that will be completed when the backend has responded</returns>
+    public static Task<Response> reportInput() {
+        return sendRequest(Request.PlayerInput());
+    }
+
     /// <summary>
     /// Sends a request to the backend to set the production mode.
     /// </summary>
@@ -507,6 +646,8 @@ public static class Client {
         if (socketResult.is_ok()) { 
             brokenPipe = false;
             LOG.Info("Reconnected to backend");
+            // The subscription belonged to the old connection
+            subscribeEvents();
             return true;
         }
 
@@ -50,6 +50,11 @@ public class DevcadeGame {
     /// </summary>
     public User user { get; set; }
 
+    /// <summary>
+    /// Whether this version of the game keeps crashing. Filled in by the backend.
+    /// </summary>
+    public bool broken { get; set; }
+
     /// <summary>
     /// The banner associated with this game.
     /// Will have the value null if the banner cannot be found
@@ -183,6 +183,10 @@ public partial class GuiManager : Control
 
     double screenSaverTimeoutSeconds;
     double screenSaverTimerSeconds;
+    // Input is reported to the backend at most this often, which keeps the session from going
+    // idle and ends attract mode without sending a request every frame
+    private const double inputReportIntervalSeconds = 1.0;
+    double inputReportTimerSeconds;
 
     [Export]
     private double secBeforeKeyRepeat = 0.3;
@@ -242,6 +246,7 @@ public partial class GuiManager : Control
         //
         // screen saver
         //
+        inputReportTimerSeconds -= delta;
         if (!Input.IsAnythingPressed())
         {
             screenSaverTimerSeconds -= delta;
@@ -260,6 +265,11 @@ public partial class GuiManager : Control
         }
         else
         {
+            if (inputReportTimerSeconds <= 0.0)
+            {
+                _ = Client.reportInput();
+                inputReportTimerSeconds = inputReportIntervalSeconds;
+            }
             if (showingScreenSaverAnimation)
             {
                 showingScreenSaverAnimation = false;
@@ -245,23 +245,22 @@ public partial class GuiManagerGlobal : Node
     }
 
     /// <summary>
-    /// Sends requests to the backend to download the banners for each game in gameTitles
+    /// Sends a request to the backend to download the banners for each game in gameTitles
     /// </summary>
     /// <returns> A task that completes when all banners are downloaded </returns>
     private Task downloadBanners()
     {
-        List<Task> bannerTasks = new();
+        List<string> ids = new();
         foreach(DevcadeGame game in gameTitles)
         {            
-            // Start downloading the textures
+            // don't download the banner for the default game
             if (game.id != "error") 
             {
-                // don't download the banner for the default game
-                 bannerTasks.Add(Client.downloadBanner(game.id));
-            } // check if /tmp/ has the banner
+                ids.Add(game.id);
+            }
         }
 
-        return Task.WhenAll(bannerTasks).WaitAsync(TimeSpan.FromSeconds(10));
+        return Client.prefetchAssets(ids).WaitAsync(TimeSpan.FromSeconds(10));
     }
 
     /// <summary>
@@ -273,7 +272,7 @@ public partial class GuiManagerGlobal : Node
         LOG.Info("loading banners");
         foreach(DevcadeGame game in gameTitles)
         {                                    
-            string bannerPath = $"{Env.DEVCADE_PATH()}/{game.id}/banner.png";
+            string bannerPath = $"{Client.gameDir(game.id)}/banner.png";
 
             if (File.Exists(bannerPath)) 
             {
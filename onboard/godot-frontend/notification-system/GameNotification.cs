@@ -0,0 +1,86 @@
+using System.Collections.Concurrent;
+using System.Collections.Generic;
+using Godot;
+using Newtonsoft.Json.Linq;
+using onboard.devcade;
+using onboard.util;
+
+/// <summary>
+/// Shows the notifications a running game asks the backend to show, and the backend's warnings that the
+/// session is about to end, one at a time.
+/// </summary>
+public partial class GameNotification : Label
+{
+    private onboard.util.Logger LOG = Log.get(nameof(GameNotification));
+
+    [Export]
+    NotificationWindow window;
+
+    [Export]
+    double lingerTime = 4.0;
+    private double lingerSec;
+
+    // Backend events arrive on the client's thread, so they are only queued there
+    private readonly ConcurrentQueue<(string text, string severity)> pending = new();
+
+    public override void _Ready()
+    {
+        this.Visible = false;
+        Client.onBackendEvent += onBackendEvent;
+    }
+
+    public override void _ExitTree()
+    {
+        Client.onBackendEvent -= onBackendEvent;
+    }
+
+    private void onBackendEvent(object sender, Dictionary<string, object> ev)
+    {
+        if ((string)ev["type"] is "GameExited" or "GameCrashed")
+        {
+            // Whatever the game still had to say doesn't matter anymore
+            pending.Clear();
+            return;
+        }
+        var data = (JObject)ev["data"];
+        switch ((string)ev["type"])
+        {
+            case "GameNotification":
+                pending.Enqueue((data["text"]!.ToObject<string>(), data["severity"]!.ToObject<string>()));
+                break;
+            case "SessionWarning":
+                ulong remaining = data["remaining_secs"]!.ToObject<ulong>();
+                string text = data["reason"]!.ToObject<string>() switch
+                {
+                    "Idle" => $"Press any button to keep playing, the game ends in {remaining}s",
+                    _ => $"Time is almost up, the game ends in {remaining}s",
+                };
+                pending.Enqueue((text, "Warning"));
+                break;
+        }
+    }
+
+    public override void _Process(double delta)
+    {
+        lingerSec += delta;
+        if(Visible && lingerSec < lingerTime) { return; }
+
+        if(!pending.TryDequeue(out var notification))
+        {
+            this.Visible = false;
+            return;
+        }
+
+        LOG.Info($"Showing {notification.severity} notification from game");
+        this.Text = notification.text;
+        this.Modulate = notification.severity switch
+        {
+            "Warning" => Colors.Yellow,
+            "Error" => Colors.Red,
+            _ => Colors.White,
+        };
+        window.show();
+        this.Visible = true;
+        lingerSec = 0.0;
+    }
+}
@@ -1,4 +1,5 @@
 #nullable enable
+using System.Collections.Generic;
 using Newtonsoft.Json;
 
 namespace onboard.util;
@@ -18,6 +19,7 @@ public class Request {
         DownloadGame,
         DownloadIcon,
         DownloadBanner,
+        PrefetchAssets,
         
         GetTagList,
         GetTag,
@@ -29,19 +31,27 @@ public class Request {
 
         LaunchGame,
         KillGame,
+        PauseGame,
+        ResumeGame,
+        GetCurrentSession,
+        SubscribeEvents,
+        PlayerInput,
     }
 
     public uint request_id { get; private set; }
     private readonly RequestType type;
     private readonly object? data;
 
-    private Request(RequestType type, string string_id = null, bool? prod = null) {
+    private Request(RequestType type, string string_id = null, bool? prod = null, List<string>? ids = null) {
         this.request_id = _id++;
         this.type = type;
         this.data = type switch {
-            RequestType.Ping or RequestType.GetGameList or RequestType.GetGameListFromFs or RequestType.GetTagList or RequestType.KillGame =>
+            RequestType.Ping or RequestType.GetGameList or RequestType.GetGameListFromFs or RequestType.GetTagList or RequestType.KillGame
+                or RequestType.PauseGame or RequestType.ResumeGame or RequestType.GetCurrentSession or RequestType.SubscribeEvents
+                or RequestType.PlayerInput =>
                 null,
             RequestType.SetProduction => prod ?? true,
+            RequestType.PrefetchAssets => ids ?? new List<string>(),
             _ => string_id ?? ""
         };
     }
@@ -79,6 +89,10 @@ public class Request {
         return new Request(RequestType.DownloadBanner, game_id);
     }
 
+    public static Request PrefetchAssets(List<string> game_ids) {
+        return new Request(RequestType.PrefetchAssets, null, null, game_ids);
+    }
+
     public static Request LaunchGame(string game_id) {
         return new Request(RequestType.LaunchGame, game_id);
     }
@@ -87,6 +101,26 @@ public class Request {
         return new Request(RequestType.KillGame);
     }
 
+    public static Request PauseGame() {
+        return new Request(RequestType.PauseGame);
+    }
+
+    public static Request ResumeGame() {
+        return new Request(RequestType.ResumeGame);
+    }
+
+    public static Request GetCurrentSession() {
+        return new Request(RequestType.GetCurrentSession);
+    }
+
+    public static Request SubscribeEvents() {
+        return new Request(RequestType.SubscribeEvents);
+    }
+
+    public static Request PlayerInput() {
+        return new Request(RequestType.PlayerInput);
+    }
+
     public static Request SetProduction(bool prod) {
         return new Request(RequestType.SetProduction, null, prod);
     }
@@ -18,12 +18,19 @@ public class Response {
         Err,
         GameList,
         Game,
+
+        AssetPrefetch,
         
         TagList,
         Tag,
         
         User,
 
+        SessionId,
+        Session,
+        Terminated,
+        Event,
+
         Unknown,
     }
     
@@ -82,6 +89,9 @@ public class Response {
             ResponseType.TagList => typeof(List<Tag>),
             ResponseType.Tag => typeof(Tag),
             ResponseType.User => typeof(User),
+            ResponseType.SessionId => typeof(ulong),
+            ResponseType.Terminated => typeof(string),
+            ResponseType.Event => typeof(Dictionary<string, object>),
             _ => throw new ArgumentOutOfRangeException()
         };
         if (typeof(T) != expected) {
@@ -94,6 +104,10 @@ public class Response {
             ResponseType.TagList => Result<T, string>.Ok(deserializeT),
             ResponseType.Tag => Result<T, string>.Ok(deserializeT),
             ResponseType.User => Result<T, string>.Ok(deserializeT),
+            ResponseType.SessionId => Result<T, string>.Ok(deserializeT),
+            ResponseType.Terminated => Result<T, string>.Ok(deserializeT),
+            ResponseType.Event => Result<T, string>.Ok(deserializeT),
+            _ => Result<T, string>.Err($"Unsupported response type {type}"),
         };
     }
 
@@ -3,6 +3,7 @@ use crate::schema::*;
 use anyhow::Error;
 use serde::{Deserialize, Serialize};
 pub use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{self, Display};
 use std::process::ExitStatus;
 use std::thread::JoinHandle;
@@ -36,6 +37,407 @@ impl From<Player> for u8 {
     }
 }
 
+/// Outcome of downloading the artwork for a single game with
+/// [`RequestBody::PrefetchAssets`]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AssetPrefetchResult {
+    /// ID of the game
+    pub game_id: String,
+    /// Why the icon couldn't be downloaded, if it couldn't be
+    pub icon_error: Option<String>,
+    /// Why the banner couldn't be downloaded, if it couldn't be
+    pub banner_error: Option<String>,
+}
+
+/// What a game session is currently doing
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub enum SessionState {
+    /// The game is being downloaded and launched
+    Starting,
+    /// The game's process has been spawned
+    Running,
+    /// The game is frozen while the frontend shows something over it
+    Paused,
+    /// The game exited cleanly
+    Exited,
+    /// The game exited with an error, or couldn't be launched
+    Crashed,
+}
+
+/// Information about a game session, from launching a game until it exits
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SessionInfo {
+    /// Unique ID of the session
+    pub session_id: u64,
+    /// ID of the game being played
+    pub game_id: String,
+    /// When the session started, in seconds since the unix epoch
+    pub started_at: u64,
+    /// What the session is currently doing
+    pub state: SessionState,
+    /// Whether the game is being shown as a demo by attract mode, rather than played
+    #[serde(default)]
+    pub demo: bool,
+}
+
+/// A player who signed in with their NFC tag while a game was running
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct SignedInPlayer {
+    /// Which controls the player is using
+    pub player: Player,
+    /// Handle of the player. It stays the same every time the player signs in to the same game,
+    /// but differs between games.
+    pub handle: String,
+}
+
+/// What a game can learn about the session it is running in
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GameSessionInfo {
+    /// ID of the game being played
+    pub game_id: String,
+    /// Hash of the version of the game being played
+    pub game_hash: Option<String>,
+    /// Unique ID of the session
+    pub session_id: u64,
+    /// Players that have signed in during the session
+    pub players: Vec<SignedInPlayer>,
+    /// Name of the cabinet the game is running on
+    pub machine_name: String,
+    /// Whether the game is being shown as a demo by attract mode, rather than played
+    pub demo: bool,
+    /// Volume of the cabinet in percent, if it could be read
+    pub volume: Option<u8>,
+}
+
+/// How a game session ended
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", content = "data")]
+pub enum ExitKind {
+    /// The game exited with status 0
+    Clean,
+    /// The game exited with a non-zero status code
+    NonZero(i32),
+    /// The game was killed by a signal
+    Signal(i32),
+    /// The game couldn't be launched, with the reason why
+    LaunchFailure(String),
+    /// The game ran into one of its resource limits, e.g. memory, and exited because of it
+    ResourceLimit(String),
+}
+
+impl Display for ExitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Clean => write!(f, "exited cleanly"),
+            Self::NonZero(code) => write!(f, "exited with status {code}"),
+            Self::Signal(signal) => write!(f, "killed by signal {signal}"),
+            Self::LaunchFailure(reason) => write!(f, "failed to launch: {reason}"),
+            Self::ResourceLimit(reason) => write!(f, "exceeded a resource limit: {reason}"),
+        }
+    }
+}
+
+/// Why the session watchdog is ending a game
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchdogReason {
+    /// The session has gone on for longer than the maximum session length
+    MaxSessionLength,
+    /// Nothing has sent a heartbeat for longer than the idle timeout
+    Idle,
+}
+
+impl Display for WatchdogReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MaxSessionLength => write!(f, "maximum session length reached"),
+            Self::Idle => write!(f, "idle for too long"),
+        }
+    }
+}
+
+/// How urgent a notification sent by a game is
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Info => write!(f, "info"),
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// How far the backend had to go to stop a game
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminationStage {
+    /// The game wasn't running anymore
+    AlreadyExited,
+    /// The game exited by itself after being sent SIGTERM
+    Terminated,
+    /// The game had to be sent SIGKILL after the grace period
+    Killed,
+    /// The sandbox was still there after SIGKILL and had to be removed with `flatpak kill`
+    FlatpakKilled,
+}
+
+/// Kind of problem [`RequestBody::VerifyInstallations`] can find with an installed game
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstallProblem {
+    /// Something the game needs isn't there, like its bundle, artwork or flatpak app
+    Missing,
+    /// The game is installed but isn't in the catalog anymore
+    Orphaned,
+    /// A newer version of the game is in the catalog
+    Outdated,
+    /// Something the game needs is there but can't be used, like an unreadable `game.json`
+    Corrupt,
+}
+
+/// Something wrong with an installed game, found by [`RequestBody::VerifyInstallations`]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstallIssue {
+    /// ID of the game, which is the name of its directory
+    pub game_id: String,
+    pub problem: InstallProblem,
+    /// What exactly is wrong
+    pub detail: String,
+    /// Whether the issue was repaired, if repairs were asked for
+    pub repaired: bool,
+    /// Why the issue couldn't be repaired, if it couldn't be
+    pub repair_error: Option<String>,
+}
+
+/// Result of [`RequestBody::VerifyInstallations`]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct InstallReport {
+    /// Number of game directories that were checked
+    pub games_checked: usize,
+    /// Whether installs were compared with the catalog. If the API can't be reached, orphaned and
+    /// outdated games aren't reported.
+    pub catalog_checked: bool,
+    pub issues: Vec<InstallIssue>,
+}
+
+/// Result of [`RequestBody::CollectGarbage`]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GcReport {
+    /// Bundles and leftovers of interrupted installs that were deleted
+    pub files_removed: Vec<String>,
+    /// Flatpak apps that were uninstalled because no installed game uses them
+    pub apps_uninstalled: Vec<String>,
+    /// Flatpak runtimes that were uninstalled because no installed app uses them. Runtimes are only
+    /// collected when games have a separate flatpak installation.
+    pub runtimes_uninstalled: Vec<String>,
+    /// Disk space that was freed, in bytes
+    pub bytes_reclaimed: u64,
+}
+
+/**
+ * Rules for which flatpak permissions games may ask for, and which are given to them when they
+ * are launched. Permissions are grouped by the realm (key) they appear under in the `[Context]`
+ * group of a flatpak's metadata, like `sockets` or `filesystems`.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct SandboxPolicy {
+    /// Permissions games may ask for, by realm
+    pub allowed: BTreeMap<String, BTreeSet<String>>,
+    /// Whether games asking for anything in a realm that isn't in `allowed` are refused. If this
+    /// is false, unknown realms are allowed entirely.
+    pub deny_unknown_realms: bool,
+    /// Arguments passed to `flatpak run` to grant permissions when launching a game
+    pub launch_args: Vec<String>,
+    /// Environment variables games may ask to be launched with. Entries ending in `*` allow every
+    /// variable starting with the rest of the entry.
+    pub allowed_env: BTreeSet<String>,
+    /// Exceptions for specific games, by game ID, on top of the rules above
+    pub games: BTreeMap<String, GamePolicy>,
+}
+
+/// Extra permissions for a specific game
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct GamePolicy {
+    /// Permissions this game may ask for on top of the global ones, by realm
+    pub allowed: BTreeMap<String, BTreeSet<String>>,
+    /// Arguments passed to `flatpak run` for this game on top of the global ones
+    pub launch_args: Vec<String>,
+    /// Environment variables this game may ask for on top of the global ones
+    pub allowed_env: BTreeSet<String>,
+}
+
+/// The default policy allows no permissions at all
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        Self {
+            allowed: BTreeMap::new(),
+            deny_unknown_realms: true,
+            launch_args: Vec::new(),
+            allowed_env: BTreeSet::new(),
+            games: BTreeMap::new(),
+        }
+    }
+}
+
+/// The captured output of a game session
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GameLog {
+    /// ID of the game the log belongs to
+    pub game_id: String,
+    /// Session the log was captured from
+    pub session_id: u64,
+    /// Everything the game wrote to stdout and stderr, up to the size cap
+    pub contents: String,
+    /// All sessions of the game that have logs, oldest first
+    pub sessions: Vec<u64>,
+}
+
+/// How much a game has been played on the cabinet, from [`RequestBody::GetGameStats`]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct GameStats {
+    /// ID of the game
+    pub game_id: String,
+    /// How many times the game has been launched
+    pub launches: u64,
+    /// How long the game has been played in total, in milliseconds
+    pub total_play_ms: u64,
+    /// How long a session of the game lasts on average, in milliseconds
+    pub average_play_ms: u64,
+    /// When the game was last launched, in seconds since the unix epoch
+    pub last_played: Option<u64>,
+    /// How many sessions of the game ended in a crash
+    pub crashes: u64,
+}
+
+/**
+ * Something that happened in the backend that the frontend didn't ask about. Events are sent to
+ * the frontend after it sends [`RequestBody::SubscribeEvents`], as responses with the request ID
+ * of the subscription.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", content = "data")]
+pub enum Event {
+    /// A game is being downloaded and launched
+    GameStarting {
+        session_id: u64,
+        game_id: String,
+        /// Whether the game is being shown as a demo by attract mode, rather than played
+        #[serde(default)]
+        demo: bool,
+    },
+    /// A game's process has been spawned
+    GameRunning { session_id: u64, game_id: String },
+    /// A game exited cleanly
+    GameExited {
+        session_id: u64,
+        game_id: String,
+        exit_code: Option<i32>,
+        duration_ms: u64,
+    },
+    /// A game exited with an error, was killed by a signal, or couldn't be launched
+    GameCrashed {
+        session_id: u64,
+        game_id: String,
+        reason: String,
+        exit: ExitKind,
+        duration_ms: u64,
+        /// How many times in a row this version of the game has crashed
+        consecutive_crashes: u32,
+        /// Whether this version of the game has crashed too often and is now marked broken
+        broken: bool,
+    },
+    /// The watchdog is going to end a session soon unless something changes
+    SessionWarning {
+        session_id: u64,
+        game_id: String,
+        reason: WatchdogReason,
+        remaining_secs: u64,
+    },
+    /// The watchdog is ending a session and returning to the menu
+    SessionTerminated {
+        session_id: u64,
+        game_id: String,
+        reason: WatchdogReason,
+    },
+    /// The game asked to be shown a message in the notification overlay
+    GameNotification {
+        session_id: u64,
+        game_id: String,
+        text: String,
+        severity: Severity,
+    },
+    /// The game asked to quit back to the menu and is being stopped
+    GameExitRequested { session_id: u64, game_id: String },
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GameStarting {
+                session_id,
+                game_id,
+                demo: false,
+            } => write!(f, "Session {session_id} starting game '{game_id}'"),
+            Self::GameStarting {
+                session_id,
+                game_id,
+                demo: true,
+            } => write!(f, "Session {session_id} starting demo of game '{game_id}'"),
+            Self::GameRunning {
+                session_id,
+                game_id,
+            } => write!(f, "Session {session_id} running game '{game_id}'"),
+            Self::GameExited {
+                session_id,
+                game_id,
+                duration_ms,
+                ..
+            } => write!(
+                f,
+                "Session {session_id} game '{game_id}' exited after {duration_ms}ms"
+            ),
+            Self::GameCrashed {
+                session_id,
+                game_id,
+                reason,
+                ..
+            } => write!(f, "Session {session_id} game '{game_id}' crashed: {reason}"),
+            Self::SessionWarning {
+                session_id,
+                reason,
+                remaining_secs,
+                ..
+            } => write!(
+                f,
+                "Session {session_id} ends in {remaining_secs}s: {reason}"
+            ),
+            Self::SessionTerminated {
+                session_id, reason, ..
+            } => write!(f, "Session {session_id} terminated: {reason}"),
+            Self::GameNotification {
+                session_id,
+                text,
+                severity,
+                ..
+            } => write!(f, "Session {session_id} {severity} notification: {text}"),
+            Self::GameExitRequested {
+                session_id,
+                game_id,
+            } => write!(
+                f,
+                "Session {session_id} game '{game_id}' asked to exit to the menu"
+            ),
+        }
+    }
+}
+
 /**
  * A request received by the backend from the frontend.
  */
@@ -44,6 +446,10 @@ pub struct Request {
     /// A unique id for this request. This ID will be sent in
     /// the [`Response::request_id`] field for the response to this request
     pub request_id: u32,
+    /// Token of the session the request is sent from. Games get it in `DEVCADE_SESSION_TOKEN`
+    /// and have to send it with every request on the game socket.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
     /// Body of this request, contains arguments relevant to the command being
     /// run
     #[serde(flatten)]
@@ -61,10 +467,14 @@ pub enum RequestBody {
     // --- Onboard backend ---
     GetGameList,
     GetGameListFromFs,
-    GetGame(String),        // String is the game ID
-    DownloadGame(String),   // String is the game ID
-    DownloadIcon(String),   // String is the game ID
-    DownloadBanner(String), // String is the game ID
+    GetGame(String),           // String is the game ID
+    DownloadGame(String),      // String is the game ID
+    RollbackGame(String),      // String is the game ID
+    VerifyInstallations(bool), // Whether to repair what is wrong
+    CollectGarbage,
+    DownloadIcon(String),                // String is the game ID
+    DownloadBanner(String),              // String is the game ID
+    PrefetchAssets(Option<Vec<String>>), // Game IDs, or None for every game
 
     GetTagList,
     GetTag(String),             // String is the tag name
@@ -76,6 +486,16 @@ pub enum RequestBody {
 
     LaunchGame(String), // String is the game
     KillGame,
+    PauseGame,
+    ResumeGame,
+    GetCurrentSession,
+    Heartbeat,                        // Keeps the current session from timing out
+    GetPolicy(Option<String>),        // Game ID to get the rules for a specific game, None for all
+    GetGameLogs(String, Option<u64>), // Game ID, session ID (latest if None)
+    GetGameStats(String),             // String is the game ID
+    GetAllStats,
+    PlayerInput,     // A player pressed something, ends attract mode
+    SubscribeEvents, // Responses keep coming with this request ID
     // ---
 
     // --- Persistence ---
@@ -84,6 +504,12 @@ pub enum RequestBody {
     Flush,
     // ---
 
+    // --- Game ---
+    ExitToMenu,
+    Notify(String, Severity), // Text, severity
+    GetSessionInfo,
+    // ---
+
     // --- Gatekeeper ---
     GetNfcTag(Player), // u8 is the index of the reader. Right now just 0.
     GetNfcUser(String), // String is the association ID
@@ -101,17 +527,34 @@ impl RequestBody {
             Self::GetGameListFromFs,
             Self::GetGame(String::new()),
             Self::DownloadGame(String::new()),
+            Self::RollbackGame(String::new()),
+            Self::VerifyInstallations(false),
+            Self::CollectGarbage,
             Self::DownloadIcon(String::new()),
             Self::DownloadBanner(String::new()),
+            Self::PrefetchAssets(None),
             Self::GetTagList,
             Self::GetTag(String::new()),
             Self::GetGameListFromTag(String::new()),
             Self::SetProduction(false),
             Self::LaunchGame(String::new()),
             Self::KillGame,
+            Self::PauseGame,
+            Self::ResumeGame,
+            Self::GetCurrentSession,
+            Self::GetPolicy(None),
+            Self::Heartbeat,
+            Self::GetGameLogs(String::new(), None),
+            Self::GetGameStats(String::new()),
+            Self::GetAllStats,
+            Self::PlayerInput,
+            Self::SubscribeEvents,
             Self::Save(String::new(), String::new(), String::new()),
             Self::Load(String::new(), String::new()),
             Self::Flush,
+            Self::ExitToMenu,
+            Self::Notify(String::new(), Severity::Info),
+            Self::GetSessionInfo,
             Self::GetNfcTag(Player::P1),
             Self::GetNfcUser(String::new()),
         ]
@@ -142,7 +585,9 @@ pub enum ResponseBody {
     Err(String),
 
     GameList(Vec<DevcadeGame>),
-    Game(DevcadeGame),
+    Game(Box<DevcadeGame>),
+
+    AssetPrefetch(Vec<AssetPrefetchResult>),
 
     TagList(Vec<Tag>),
     Tag(Tag),
@@ -154,6 +599,18 @@ pub enum ResponseBody {
     NfcTag(Option<String>),
     NfcUser(Map<String, Value>),
 
+    SessionId(u64),
+    Session(Option<SessionInfo>),
+    GameSessionInfo(GameSessionInfo),
+    GameLog(GameLog),
+    GameStats(GameStats),
+    AllStats(Vec<GameStats>),
+    Terminated(TerminationStage),
+    Policy(SandboxPolicy),
+    InstallReport(InstallReport),
+    GcReport(GcReport),
+    Event(Event),
+
     #[serde(skip)]
     InternalGame(JoinHandle<ExitStatus>),
 }
@@ -174,7 +631,8 @@ impl ResponseBody {
             Self::Ok,
             Self::Err(String::new()),
             Self::GameList(Vec::new()),
-            Self::Game(DevcadeGame::default()),
+            Self::Game(Box::default()),
+            Self::AssetPrefetch(Vec::new()),
             Self::TagList(Vec::new()),
             Self::Tag(Tag::default()),
             Self::User(User::default()),
@@ -182,6 +640,26 @@ impl ResponseBody {
             Self::InternalGame(std::thread::spawn(|| std::process::exit(0))),
             Self::NfcTag(None),
             Self::NfcUser(Map::default()),
+            Self::SessionId(0),
+            Self::Session(None),
+            Self::GameSessionInfo(GameSessionInfo::default()),
+            Self::GameLog(GameLog {
+                game_id: String::new(),
+                session_id: 0,
+                contents: String::new(),
+                sessions: Vec::new(),
+            }),
+            Self::GameStats(GameStats::default()),
+            Self::AllStats(Vec::new()),
+            Self::Terminated(TerminationStage::Terminated),
+            Self::Policy(SandboxPolicy::default()),
+            Self::InstallReport(InstallReport::default()),
+            Self::GcReport(GcReport::default()),
+            Self::Event(Event::GameStarting {
+                session_id: 0,
+                game_id: String::new(),
+                demo: false,
+            }),
         ]
     }
 }
@@ -198,18 +676,49 @@ impl Display for RequestBody {
             Self::DownloadGame(game_id) => {
                 write!(f, "Download game with id '{game_id}'")
             }
+            Self::RollbackGame(game_id) => {
+                write!(
+                    f,
+                    "Roll back game with id '{game_id}' to its previous version"
+                )
+            }
+            Self::VerifyInstallations(true) => write!(f, "Verify and repair installed games"),
+            Self::VerifyInstallations(false) => write!(f, "Verify installed games"),
+            Self::CollectGarbage => write!(f, "Delete unused bundles, apps and runtimes"),
             Self::DownloadIcon(game_id) => {
                 write!(f, "Download icon with id '{game_id}'")
             }
             Self::DownloadBanner(game_id) => {
                 write!(f, "Download banner with id '{game_id}'")
             }
+            Self::PrefetchAssets(Some(game_ids)) => {
+                write!(f, "Prefetch artwork for {} games", game_ids.len())
+            }
+            Self::PrefetchAssets(None) => write!(f, "Prefetch artwork for all games"),
             Self::LaunchGame(game_id) => {
                 write!(f, "Launch game with id '{game_id}'")
             }
             Self::KillGame => {
                 write!(f, "Kill currently running game")
             }
+            Self::PauseGame => write!(f, "Pause currently running game"),
+            Self::ResumeGame => write!(f, "Resume currently paused game"),
+            Self::GetCurrentSession => write!(f, "Get current game session"),
+            Self::Heartbeat => write!(f, "Heartbeat"),
+            Self::GetPolicy(Some(game_id)) => {
+                write!(f, "Get sandbox policy for game '{game_id}'")
+            }
+            Self::GetPolicy(None) => write!(f, "Get sandbox policy"),
+            Self::GetGameLogs(game_id, Some(session_id)) => {
+                write!(f, "Get log of session {session_id} of game '{game_id}'")
+            }
+            Self::GetGameLogs(game_id, None) => {
+                write!(f, "Get latest log of game '{game_id}'")
+            }
+            Self::GetGameStats(game_id) => write!(f, "Get play statistics of game '{game_id}'"),
+            Self::GetAllStats => write!(f, "Get play statistics of all games"),
+            Self::PlayerInput => write!(f, "Player input"),
+            Self::SubscribeEvents => write!(f, "Subscribe to backend events"),
             Self::SetProduction(prod) => {
                 write!(
                     f,
@@ -226,6 +735,9 @@ impl Display for RequestBody {
             Self::Save(group, key, _value) => write!(f, "Save value to {group}/{key}"),
             Self::Load(group, key) => write!(f, "Load value from {group}/{key}"),
             Self::Flush => write!(f, "Flush cached save data"),
+            Self::ExitToMenu => write!(f, "Exit to menu"),
+            Self::Notify(text, severity) => write!(f, "Show {severity} notification: {text}"),
+            Self::GetSessionInfo => write!(f, "Get info about the game's session"),
             Self::GetNfcTag(player) => {
                 write!(f, "Get NFC tags for player '{player}'")
             }
@@ -262,8 +774,11 @@ impl Display for ResponseBody {
             Self::GameList(games) => {
                 write!(f, "Got game list with {} games", games.len())
             }
-            Self::Game(DevcadeGame { id, .. }) => {
-                write!(f, "Downloaded game with id '{}'", id)
+            Self::Game(game) => {
+                write!(f, "Downloaded game with id '{}'", game.id)
+            }
+            Self::AssetPrefetch(results) => {
+                write!(f, "Prefetched artwork for {} games", results.len())
             }
             Self::InternalGame(_) => write!(f, "Launched game"),
             Self::TagList(tags) => {
@@ -280,6 +795,57 @@ impl Display for ResponseBody {
             Self::NfcUser(user) => {
                 write!(f, "Got NFC user '{:?}'", user["uid"].as_str())
             }
+            Self::SessionId(session_id) => write!(f, "Started session {session_id}"),
+            Self::Session(Some(session)) => {
+                write!(
+                    f,
+                    "Got session {} for game '{}'",
+                    session.session_id, session.game_id
+                )
+            }
+            Self::Session(None) => write!(f, "No game session is running"),
+            Self::GameSessionInfo(info) => write!(
+                f,
+                "Got info about session {} of game '{}' ({} players signed in)",
+                info.session_id,
+                info.game_id,
+                info.players.len()
+            ),
+            Self::GameLog(log) => write!(
+                f,
+                "Got log of session {} of game '{}' ({} bytes)",
+                log.session_id,
+                log.game_id,
+                log.contents.len()
+            ),
+            Self::GameStats(stats) => write!(
+                f,
+                "Got play statistics of game '{}' ({} launches)",
+                stats.game_id, stats.launches
+            ),
+            Self::AllStats(stats) => write!(f, "Got play statistics of {} games", stats.len()),
+            Self::Terminated(stage) => write!(f, "Stopped game ({stage:?})"),
+            Self::Policy(policy) => write!(
+                f,
+                "Got sandbox policy with {} realms and {} game exceptions",
+                policy.allowed.len(),
+                policy.games.len()
+            ),
+            Self::InstallReport(report) => write!(
+                f,
+                "Checked {} installed games, found {} issues",
+                report.games_checked,
+                report.issues.len()
+            ),
+            Self::GcReport(report) => write!(
+                f,
+                "Removed {} files, {} apps and {} runtimes, reclaiming {} bytes",
+                report.files_removed.len(),
+                report.apps_uninstalled.len(),
+                report.runtimes_uninstalled.len(),
+                report.bytes_reclaimed
+            ),
+            Self::Event(event) => write!(f, "Event: {event}"),
         }
     }
 }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /**
  * A tag from the Devcade API that is associated with a game. Used to categorize games.
@@ -124,6 +125,53 @@ pub struct DevcadeGame {
 
     /// Flatpak app id for the game
     pub flatpak_app_id: Option<String>,
+
+    /// Commit of the flatpak app that was installed for the game. Only filled in by the backend.
+    #[serde(default)]
+    pub flatpak_commit: Option<String>,
+
+    /// Extra arguments and environment the author wants the game launched with
+    #[serde(default)]
+    pub launch: LaunchOptions,
+
+    /// Artwork the backend has downloaded for the game. Only filled in by the backend.
+    pub artwork: Option<Box<GameArtwork>>,
+
+    /// Whether this version of the game keeps crashing. Only filled in by the backend.
+    #[serde(default)]
+    pub broken: bool,
+}
+
+/**
+ * Extra arguments and environment variables a game is launched with. Environment variables are
+ * only set if the sandbox policy allows their names.
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct LaunchOptions {
+    /// Arguments passed to the game
+    pub args: Vec<String>,
+    /// Environment variables set for the game
+    pub env: BTreeMap<String, String>,
+}
+
+/**
+ * Paths to the artwork the backend has downloaded for a game. Each path is `None` if that file
+ * hasn't been downloaded yet.
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct GameArtwork {
+    /// The icon, as uploaded by the author, converted to PNG.
+    pub icon: Option<String>,
+
+    /// The icon, scaled and cropped to a fixed size square PNG.
+    pub icon_thumbnail: Option<String>,
+
+    /// The banner, as uploaded by the author, converted to PNG.
+    pub banner: Option<String>,
+
+    /// The banner as a PNG, scaled down if it is very large.
+    pub banner_small: Option<String>,
 }
 
 /**